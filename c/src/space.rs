@@ -349,14 +349,27 @@ struct CObserver {
     payload: *mut c_void,
 }
 
-impl SpaceObserver for CObserver {
-    fn notify(&mut self, event: &SpaceEvent) {
+impl CObserver {
+    fn notify_c(&mut self, event: &SpaceEvent) {
         let api = unsafe{ &*self.api };
         let event = space_event_t::ref_wrapper(event);
         (api.notify)(self.payload, &event);
     }
 }
 
+impl SpaceObserver for CObserver {
+    fn notify(&mut self, event: &SpaceEvent) {
+        match event {
+            // C API represents batch events as a sequence of single atom events
+            SpaceEvent::BatchAdd(atoms) => atoms.iter()
+                .for_each(|atom| self.notify_c(&SpaceEvent::Add(atom.clone()))),
+            SpaceEvent::BatchRemove(atoms) => atoms.iter()
+                .for_each(|atom| self.notify_c(&SpaceEvent::Remove(atom.clone()))),
+            _ => self.notify_c(event),
+        }
+    }
+}
+
 impl Drop for CObserver {
     fn drop(&mut self) {
         let api = unsafe{ &*self.api };
@@ -404,6 +417,9 @@ pub extern "C" fn space_event_get_type(event: *const space_event_t) -> space_eve
         SpaceEvent::Add(_) => space_event_type_t::SPACE_EVENT_TYPE_ADD,
         SpaceEvent::Remove(_) => space_event_type_t::SPACE_EVENT_TYPE_REMOVE,
         SpaceEvent::Replace(_, _) => space_event_type_t::SPACE_EVENT_TYPE_REPLACE,
+        // C code gets events only from CObserver::notify which expands batch
+        // events into a sequence of single atom events
+        SpaceEvent::BatchAdd(_) | SpaceEvent::BatchRemove(_) => unreachable!(),
     }
 }
 
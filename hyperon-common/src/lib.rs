@@ -11,35 +11,143 @@ pub mod vecondemand;
 mod flex_ref;
 pub use flex_ref::FlexRef;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+
+/// Hit and miss statistics of the [CachingMapper].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CachingMapperStats {
+    /// Number of accesses which returned cached value
+    pub hits: u64,
+    /// Number of accesses which called mapper
+    pub misses: u64,
+}
 
 #[derive(Clone)]
 pub struct CachingMapper<K: Clone + std::hash::Hash + Eq + ?Sized, V: Clone, F: Fn(K) -> V> {
     mapper: F,
     mapping: HashMap<K, V>,
+    capacity: Option<usize>,
+    // Stamp of the last access of each cached key and queue of the accesses,
+    // the least recent access is the first one. Access in the queue is stale
+    // when the key was accessed again after it, stale accesses are skipped on
+    // eviction. Both are kept only when capacity is set.
+    stamps: HashMap<K, u64>,
+    order: VecDeque<(K, u64)>,
+    next_stamp: u64,
+    stats: CachingMapperStats,
 }
 
 impl<K: Clone + std::hash::Hash + Eq + ?Sized, V: Clone, F: Fn(K) -> V> CachingMapper<K, V, F> {
     pub fn new(mapper: F) -> Self {
-        Self{ mapper, mapping: HashMap::new() }
+        Self{ mapper, mapping: HashMap::new(), capacity: None, stamps: HashMap::new(),
+            order: VecDeque::new(), next_stamp: 0, stats: CachingMapperStats::default() }
+    }
+
+    /// Constructs mapper which keeps not more than `capacity` cached values.
+    /// When new value doesn't fit the least recently used one is evicted.
+    pub fn with_capacity(mapper: F, capacity: usize) -> Self {
+        Self{ mapper, mapping: HashMap::new(), capacity: Some(capacity), stamps: HashMap::new(),
+            order: VecDeque::new(), next_stamp: 0, stats: CachingMapperStats::default() }
     }
 
     pub fn replace(&mut self, key: K) -> V {
         match self.mapping.get(&key) {
-            Some(mapped) => mapped.clone(),
+            Some(mapped) => {
+                let mapped = mapped.clone();
+                self.stats.hits += 1;
+                self.touch(key);
+                mapped
+            },
             None => {
                 let new_val = (self.mapper)(key.clone());
+                self.stats.misses += 1;
+                if let Some(capacity) = self.capacity {
+                    if capacity == 0 {
+                        return new_val;
+                    }
+                    while self.mapping.len() >= capacity && self.evict_lru() {}
+                    self.stamps.insert(key.clone(), 0);
+                    self.touch(key.clone());
+                }
                 self.mapping.insert(key, new_val.clone());
                 new_val
             }
         }
     }
 
+    fn touch(&mut self, key: K) {
+        if let Some(capacity) = self.capacity {
+            let stamp = self.next_stamp;
+            self.next_stamp += 1;
+            if let Some(last) = self.stamps.get_mut(&key) {
+                *last = stamp;
+                self.order.push_back((key, stamp));
+            }
+            // Drop stale accesses to keep the queue size proportional to
+            // capacity, it keeps touch amortized constant time.
+            if self.order.len() > 2 * capacity {
+                let stamps = &self.stamps;
+                self.order.retain(|(key, stamp)| stamps.get(key) == Some(stamp));
+            }
+        }
+    }
+
+    fn evict_lru(&mut self) -> bool {
+        while let Some((key, stamp)) = self.order.pop_front() {
+            if self.stamps.get(&key) == Some(&stamp) {
+                self.stamps.remove(&key);
+                self.mapping.remove(&key);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Returns cached value of the `key` if it is present. Unlike
+    /// [CachingMapper::replace] it never calls mapper and doesn't change
+    /// the access order.
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        self.mapping.get(key)
+    }
+
+    /// Removes all cached values, they are recomputed on the next access.
+    pub fn clear(&mut self) {
+        self.mapping.clear();
+        self.stamps.clear();
+        self.order.clear();
+    }
+
+    /// Removes cached value of the `key` and returns it. Value is recomputed
+    /// on the next access.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.stamps.remove(key);
+        self.mapping.remove(key)
+    }
+
+    /// Returns hit and miss statistics collected by [CachingMapper::replace]
+    /// since construction or the last [CachingMapper::reset_stats] call.
+    pub fn stats(&self) -> CachingMapperStats {
+        self.stats
+    }
+
+    /// Resets hit and miss statistics.
+    pub fn reset_stats(&mut self) {
+        self.stats = CachingMapperStats::default();
+    }
+
     pub fn mapping(&self) -> &HashMap<K, V> {
         &self.mapping
     }
 
+    /// Returns mutable reference to the cached values. Changes made via
+    /// the reference bypass the access order bookkeeping, thus it is
+    /// available only for mappers constructed by [CachingMapper::new].
+    ///
+    /// # Panics
+    ///
+    /// Panics when the mapper is constructed by [CachingMapper::with_capacity].
     pub fn mapping_mut(&mut self) -> &mut HashMap<K, V> {
+        assert!(self.capacity.is_none(), "mapping_mut() is not supported by the mapper with capacity");
         &mut self.mapping
     }
 
@@ -47,3 +155,149 @@ impl<K: Clone + std::hash::Hash + Eq + ?Sized, V: Clone, F: Fn(K) -> V> CachingM
         move |k| { self.replace(k) }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn caching_mapper_unbounded() {
+        let mut mapper = CachingMapper::new(|k: u32| k * 2);
+
+        for k in 0..100 {
+            assert_eq!(mapper.replace(k), k * 2);
+        }
+
+        assert_eq!(mapper.mapping().len(), 100);
+    }
+
+    #[test]
+    fn caching_mapper_evicts_least_recently_used() {
+        let mut mapper = CachingMapper::with_capacity(|k: u32| k * 2, 2);
+
+        mapper.replace(1);
+        mapper.replace(2);
+        mapper.replace(1);
+        mapper.replace(3);
+
+        assert_eq!(mapper.mapping().len(), 2);
+        assert!(mapper.mapping().contains_key(&1));
+        assert!(!mapper.mapping().contains_key(&2));
+        assert!(mapper.mapping().contains_key(&3));
+
+        mapper.replace(4);
+
+        assert!(!mapper.mapping().contains_key(&1));
+        assert!(mapper.mapping().contains_key(&3));
+        assert!(mapper.mapping().contains_key(&4));
+    }
+
+    #[test]
+    fn caching_mapper_hits_keep_access_queue_bounded() {
+        let mut mapper = CachingMapper::with_capacity(|k: u32| k * 2, 2);
+
+        mapper.replace(1);
+        mapper.replace(2);
+        for _ in 0..1000 {
+            mapper.replace(1);
+        }
+        assert!(mapper.order.len() <= 4);
+
+        mapper.replace(3);
+
+        assert!(mapper.mapping().contains_key(&1));
+        assert!(!mapper.mapping().contains_key(&2));
+        assert!(mapper.mapping().contains_key(&3));
+    }
+
+    #[test]
+    fn caching_mapper_peek() {
+        let calls = std::cell::Cell::new(0);
+        let mut mapper = CachingMapper::new(|k: u32| { calls.set(calls.get() + 1); k * 2 });
+
+        mapper.replace(1);
+
+        assert_eq!(mapper.peek(&1), Some(&2));
+        assert_eq!(mapper.peek(&2), None);
+        assert_eq!(calls.get(), 1);
+        assert_eq!(mapper.mapping().len(), 1);
+    }
+
+    #[test]
+    fn caching_mapper_remove() {
+        let calls = std::cell::Cell::new(0);
+        let mut mapper = CachingMapper::new(|k: u32| { calls.set(calls.get() + 1); k * 2 });
+
+        mapper.replace(1);
+        mapper.replace(1);
+        assert_eq!(calls.get(), 1);
+
+        assert_eq!(mapper.remove(&1), Some(2));
+        assert_eq!(mapper.remove(&1), None);
+        assert_eq!(mapper.as_fn_mut()(1), 2);
+        assert_eq!(calls.get(), 2);
+    }
+
+    #[test]
+    fn caching_mapper_clear() {
+        let calls = std::cell::Cell::new(0);
+        let mut mapper = CachingMapper::with_capacity(|k: u32| { calls.set(calls.get() + 1); k * 2 }, 2);
+
+        mapper.replace(1);
+        mapper.replace(2);
+        mapper.clear();
+        assert!(mapper.mapping().is_empty());
+
+        let mut f = mapper.as_fn_mut();
+        assert_eq!(f(1), 2);
+        assert_eq!(f(2), 4);
+        assert_eq!(f(2), 4);
+        assert_eq!(calls.get(), 4);
+    }
+
+    #[test]
+    fn caching_mapper_stats() {
+        let mut mapper = CachingMapper::new(|k: u32| k * 2);
+
+        mapper.replace(1);
+        mapper.replace(2);
+        mapper.replace(1);
+        {
+            let mut f = mapper.as_fn_mut();
+            f(1);
+            f(3);
+        }
+        mapper.peek(&2);
+
+        assert_eq!(mapper.stats(), CachingMapperStats{ hits: 2, misses: 3 });
+        mapper.reset_stats();
+        assert_eq!(mapper.stats(), CachingMapperStats::default());
+        mapper.replace(3);
+        assert_eq!(mapper.stats(), CachingMapperStats{ hits: 1, misses: 0 });
+    }
+
+    #[test]
+    fn caching_mapper_mapping_mut() {
+        let mut mapper = CachingMapper::new(|k: u32| k * 2);
+
+        mapper.mapping_mut().insert(1, 3);
+
+        assert_eq!(mapper.replace(1), 3);
+    }
+
+    #[test]
+    #[should_panic]
+    fn caching_mapper_mapping_mut_with_capacity() {
+        let mut mapper = CachingMapper::with_capacity(|k: u32| k * 2, 2);
+
+        mapper.mapping_mut();
+    }
+
+    #[test]
+    fn caching_mapper_zero_capacity() {
+        let mut mapper = CachingMapper::with_capacity(|k: u32| k * 2, 0);
+
+        assert_eq!(mapper.replace(1), 2);
+        assert!(mapper.mapping().is_empty());
+    }
+}
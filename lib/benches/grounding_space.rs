@@ -36,3 +36,21 @@ fn query_x100(bencher: &mut Bencher) {
         assert_eq!(res, bind_set![{ X: Atom::sym("arg") }]);
     })
 }
+
+#[bench]
+fn query_limited_x1000(bencher: &mut Bencher) {
+    let space = space(1000);
+    bencher.iter(|| {
+        let res = space.query_limited(&expr!("=" (f x) x), 10);
+        assert_eq!(res.len(), 10);
+    })
+}
+
+#[bench]
+fn query_unlimited_x1000(bencher: &mut Bencher) {
+    let space = space(1000);
+    bencher.iter(|| {
+        let res = space.query(&expr!("=" (f x) x));
+        assert_eq!(res.len(), 1000);
+    })
+}
@@ -598,7 +598,10 @@ fn query(space: &DynSpace, prev: Option<Rc<RefCell<Stack>>>, to_eval: Atom, bind
     }
     let var_x = &VariableAtom::new("X").make_unique();
     let query = Atom::expr([EQUAL_SYMBOL, to_eval.clone(), Atom::Variable(var_x.clone())]);
-    let results = space.borrow().query(&query);
+    let results = match checked_query(|| space.borrow().query(&query)) {
+        Ok(results) => results,
+        Err(err) => return finished_result(error_msg(to_eval, err.to_string()), bindings, prev),
+    };
     log::debug!("interpreter::query: query: {}", query);
     log::debug!("interpreter::query: results.len(): {}, bindings.len(): {}, results: {} bindings: {}",
         results.len(), bindings.len(), results, bindings);
@@ -1471,6 +1474,17 @@ mod tests {
         assert_eq!(result, vec![metta_atom("NotReducible")]);
     }
 
+    #[test]
+    fn interpret_atom_evaluate_atom_query_depth_exceeded() {
+        let space = space("");
+        space.borrow_mut().add(Atom::gnd(space.clone()));
+
+        let result = call_interpret(space, &metta_atom("(eval a)"));
+
+        let err = QueryDepthExceeded{ max_depth: DEFAULT_MAX_QUERY_DEPTH }.to_string();
+        assert_eq!(result, vec![error_msg(sym!("a"), err)]);
+    }
+
     #[test]
     fn interpret_atom_evaluate_empty_expression() {
         let result = call_interpret(space(""), &metta_atom("(eval ())"));
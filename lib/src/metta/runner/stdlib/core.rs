@@ -161,7 +161,8 @@ impl CustomExecute for MatchOp {
         let template = args.get(2).ok_or_else(arg_error)?;
         log::debug!("MatchOp::execute: space: {:?}, pattern: {:?}, template: {:?}", space, pattern, template);
         let space = Atom::as_gnd::<DynSpace>(space).ok_or("match expects a space as the first argument")?;
-        Ok(space.borrow().subst(&pattern, &template))
+        checked_query(|| space.borrow().subst(&pattern, &template))
+            .map_err(|err| ExecError::from(err.to_string()))
     }
 }
 
@@ -545,6 +546,15 @@ mod tests {
                 "atoms are not equivalent: expected: {}, actual: {}", expr!("A" x x), result[0]);
     }
 
+    #[test]
+    fn match_op_query_depth_exceeded() {
+        let space = metta_space("(A B)");
+        space.borrow_mut().add(Atom::gnd(space.clone()));
+        let match_op = MatchOp{};
+        let result = match_op.execute(&mut vec![expr!({space}), expr!("C" x), expr!(x)]);
+        assert_eq!(result, Err(ExecError::from(QueryDepthExceeded{ max_depth: DEFAULT_MAX_QUERY_DEPTH }.to_string())));
+    }
+
     #[test]
     fn nop_op() {
         assert_eq!(NopOp{}.execute(&mut vec![]), unit_result());
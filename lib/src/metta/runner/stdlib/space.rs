@@ -2,6 +2,9 @@ use hyperon_atom::*;
 use crate::space::*;
 use crate::metta::*;
 use crate::metta::text::Tokenizer;
+use crate::metta::runner::number::Number;
+use crate::space::grounding::GroundingSpace;
+use crate::space::grounding::index::DuplicationStrategy;
 use crate::metta::runner::stdlib::{grounded_op, unit_result, regex};
 
 use std::rc::Rc;
@@ -221,6 +224,94 @@ impl CustomExecute for RemoveAtomOp {
     }
 }
 
+#[derive(Clone, Debug)]
+pub struct AssertMatchesOp {}
+
+grounded_op!(AssertMatchesOp, "assert-matches");
+
+impl Grounded for AssertMatchesOp {
+    fn type_(&self) -> Atom {
+        Atom::expr([ARROW_SYMBOL, ATOM_TYPE_SPACE, ATOM_TYPE_ATOM,
+            ATOM_TYPE_ATOM, ATOM_TYPE_SPACE, UNIT_TYPE])
+    }
+
+    fn as_execute(&self) -> Option<&dyn CustomExecute> {
+        Some(self)
+    }
+}
+
+impl CustomExecute for AssertMatchesOp {
+    fn execute(&self, args: &[Atom]) -> Result<Vec<Atom>, ExecError> {
+        let arg_error = || ExecError::from("assert-matches expects four arguments: source space, pattern, template and target space");
+        let source = args.get(0).ok_or_else(arg_error)?;
+        let pattern = args.get(1).ok_or_else(arg_error)?;
+        let template = args.get(2).ok_or_else(arg_error)?;
+        let target = args.get(3).ok_or_else(arg_error)?;
+        let source = Atom::as_gnd::<DynSpace>(source).ok_or("assert-matches expects a space as the first argument")?;
+        let target = Atom::as_gnd::<DynSpace>(target).ok_or("assert-matches expects a space as the fourth argument")?;
+        // Results are collected before adding to allow source and target to be the same space
+        let derived = checked_query(|| source.borrow().subst(pattern, template))
+            .map_err(|err| ExecError::from(err.to_string()))?;
+        let mut target = target.borrow_mut();
+        for atom in derived {
+            target.add(atom);
+        }
+        unit_result()
+    }
+}
+
+/// Head of the counter atoms `(count <key> <value>)` which are updated by
+/// [increment_counter].
+pub const COUNTER_SYMBOL: Atom = sym!("count");
+
+/// Increments value of the `(count <key> <value>)` counter atom of the
+/// `space` by `by` and returns the new value. When there is no such counter
+/// in the space then it is added with the `by` value. Counter is found
+/// using the space index, the `key` is compared by equality, and `<value>`
+/// is an integer [Number]. Counter is updated by
+/// a single [GroundingSpace::replace] thus observers see a single
+/// [SpaceEvent::Replace] event and never see the counter missing. Returns
+/// an error and keeps the counter unchanged when its value is not an
+/// integer number or when the new value overflows `i64`.
+///
+/// # Examples
+///
+/// ```
+/// use hyperon_atom::sym;
+/// use hyperon::space::grounding::GroundingSpace;
+/// use hyperon::metta::runner::stdlib::space::increment_counter;
+///
+/// let mut space = GroundingSpace::new();
+///
+/// assert_eq!(increment_counter(&mut space, &sym!("visits"), 1), Ok(1));
+/// assert_eq!(increment_counter(&mut space, &sym!("visits"), 2), Ok(3));
+/// assert!(increment_counter(&mut space, &sym!("visits"), i64::MAX).is_err());
+/// ```
+pub fn increment_counter<D: DuplicationStrategy>(space: &mut GroundingSpace<D>, key: &Atom, by: i64) -> Result<i64, String> {
+    let var = VariableAtom::new("value").make_unique();
+    let counter = |value: Atom| Atom::expr([COUNTER_SYMBOL, key.clone(), value]);
+    // Query unifies the key, thus only exact counters of the key are kept
+    let current = space.query(&counter(Atom::Variable(var.clone()))).into_iter()
+        .filter_map(|bindings| bindings.resolve(&var))
+        .find(|value| space.contains(&counter(value.clone())));
+    match current {
+        None => {
+            space.add(counter(Atom::gnd(Number::Integer(by))));
+            Ok(by)
+        },
+        Some(value) => match Number::from_atom(&value) {
+            Some(Number::Integer(n)) => {
+                let from = counter(value);
+                let n = n.checked_add(by)
+                    .ok_or_else(|| format!("Counter {} overflows when incremented by {}", from, by))?;
+                space.replace(&from, counter(Atom::gnd(Number::Integer(n))));
+                Ok(n)
+            },
+            _ => Err(format!("Counter {} has non-integer value", counter(value))),
+        },
+    }
+}
+
 pub(super) fn register_context_independent_tokens(tref: &mut Tokenizer) {
     let new_space_op = Atom::gnd(NewSpaceOp{});
     tref.register_token(regex(r"new-space"), move |_| { new_space_op.clone() });
@@ -228,6 +319,8 @@ pub(super) fn register_context_independent_tokens(tref: &mut Tokenizer) {
     tref.register_token(regex(r"add-atom"), move |_| { add_atom_op.clone() });
     let remove_atom_op = Atom::gnd(RemoveAtomOp{});
     tref.register_token(regex(r"remove-atom"), move |_| { remove_atom_op.clone() });
+    let assert_matches_op = Atom::gnd(AssertMatchesOp{});
+    tref.register_token(regex(r"assert-matches"), move |_| { assert_matches_op.clone() });
     let new_state_op = Atom::gnd(NewStateOp{});
     tref.register_token(regex(r"new-state"), move |_| { new_state_op.clone() });
     let change_state_op = Atom::gnd(ChangeStateOp{});
@@ -259,6 +352,60 @@ mod tests {
         assert_eq!(result[2], vec![Atom::expr([Atom::gnd(super::super::module::ModSpaceOp::new(runner.clone())), Atom::sym("stdlib")])]);
     }
 
+    #[test]
+    fn increment_counter_creates_counter() {
+        let mut space = GroundingSpace::new();
+        let observer = space.common().register_observer(SpaceEventCollector::new());
+
+        assert_eq!(increment_counter(&mut space, &expr!("page" "home"), 5), Ok(5));
+
+        assert_eq!(space.atoms(), vec![expr!("count" ("page" "home") {Number::Integer(5)})]);
+        assert_eq!(observer.borrow().events, vec![SpaceEvent::Add(
+            expr!("count" ("page" "home") {Number::Integer(5)}))]);
+    }
+
+    #[test]
+    fn increment_counter_updates_counter() {
+        let mut space = GroundingSpace::from_vec(vec![
+            expr!("count" "a" {Number::Integer(1)}),
+            expr!("count" "b" {Number::Integer(10)}),
+        ]);
+        let observer = space.common().register_observer(SpaceEventCollector::new());
+
+        assert_eq!(increment_counter(&mut space, &sym!("a"), 2), Ok(3));
+        assert_eq!(increment_counter(&mut space, &sym!("a"), -5), Ok(-2));
+
+        assert_eq_no_order!(space.atoms(), vec![
+            expr!("count" "a" {Number::Integer(-2)}),
+            expr!("count" "b" {Number::Integer(10)}),
+        ]);
+        assert_eq!(observer.borrow().events, vec![
+            SpaceEvent::Replace(expr!("count" "a" {Number::Integer(1)}), expr!("count" "a" {Number::Integer(3)})),
+            SpaceEvent::Replace(expr!("count" "a" {Number::Integer(3)}), expr!("count" "a" {Number::Integer(-2)})),
+        ]);
+    }
+
+    #[test]
+    fn increment_counter_rejects_invalid_values() {
+        let mut space = GroundingSpace::from_vec(vec![
+            expr!("count" "a" {Number::Integer(i64::MAX)}),
+            expr!("count" "b" {Number::Float(1.5)}),
+            expr!("count" "c" "d"),
+        ]);
+        let observer = space.common().register_observer(SpaceEventCollector::new());
+
+        assert!(increment_counter(&mut space, &sym!("a"), 1).is_err());
+        assert!(increment_counter(&mut space, &sym!("b"), 1).is_err());
+        assert!(increment_counter(&mut space, &sym!("c"), 1).is_err());
+
+        assert_eq_no_order!(space.atoms(), vec![
+            expr!("count" "a" {Number::Integer(i64::MAX)}),
+            expr!("count" "b" {Number::Float(1.5)}),
+            expr!("count" "c" "d"),
+        ]);
+        assert_eq!(observer.borrow().events, vec![]);
+    }
+
     fn collect_atoms(space: &DynSpace) -> Vec<Atom> {
         let mut atoms = Vec::new();
         space.borrow().visit(&mut |atom: std::borrow::Cow<Atom>| atoms.push(atom.into_owned()))
@@ -312,6 +459,51 @@ mod tests {
         assert_eq_no_order!(space_atoms, vec![expr!(("foo" "bar"))]);
     }
 
+    #[test]
+    fn assert_matches_op() {
+        let source = metta_space("
+            (parent Tom Bob)
+            (parent Bob Ann)
+            (likes Tom Ann)
+        ");
+        let target = DynSpace::new(GroundingSpace::new());
+        let res = AssertMatchesOp{}.execute(&mut vec![Atom::gnd(source),
+            expr!("parent" x y), expr!("child" y x), Atom::gnd(target.clone())])
+            .expect("No result returned");
+        assert_eq!(res, vec![UNIT_ATOM]);
+        assert_eq_no_order!(collect_atoms(&target), vec![expr!("child" "Bob" "Tom"), expr!("child" "Ann" "Bob")]);
+    }
+
+    #[test]
+    fn assert_matches_op_query_depth_exceeded() {
+        let source = metta_space("(A B)");
+        source.borrow_mut().add(Atom::gnd(source.clone()));
+        let target = DynSpace::new(GroundingSpace::new());
+        let res = AssertMatchesOp{}.execute(&mut vec![Atom::gnd(source),
+            expr!("C" x), expr!("D" x), Atom::gnd(target.clone())]);
+        assert_eq!(res, Err(ExecError::from(QueryDepthExceeded{ max_depth: DEFAULT_MAX_QUERY_DEPTH }.to_string())));
+        assert_eq!(collect_atoms(&target), vec![]);
+    }
+
+    #[test]
+    fn assert_matches_op_in_metta() {
+        let program = r#"
+            (parent Tom Bob)
+            (parent Bob Ann)
+            !(bind! &derived (new-space))
+            !(assert-matches &self (parent $x $y) (child $y $x) &derived)
+            !(match &derived (child $c Tom) $c)
+            !(assert-matches &derived (child $c $p) (ancestor $p $c) &derived)
+            !(match &derived (ancestor $a Ann) $a)
+        "#;
+        let runner = Metta::new(Some(runner::environment::EnvBuilder::test_env()));
+        let result = runner.run(SExprParser::new(program)).unwrap();
+
+        assert_eq!(result[1], vec![UNIT_ATOM]);
+        assert_eq!(result[2], vec![sym!("Bob")]);
+        assert_eq!(result[4], vec![sym!("Bob")]);
+    }
+
     #[test]
     fn state_ops() {
         let result = NewStateOp{}.execute(&mut vec![expr!("A" "B")]).unwrap();
@@ -1,7 +1,7 @@
 pub mod storage;
 pub mod trie;
 
-pub use trie::{ALLOW_DUPLICATION, NO_DUPLICATION, DuplicationStrategy, AllowDuplication, NoDuplication};
+pub use trie::{ALLOW_DUPLICATION, NO_DUPLICATION, DuplicationStrategy, AllowDuplication, NoDuplication, QueryBudget};
 use trie::*;
 
 use hyperon_atom::*;
@@ -9,6 +9,7 @@ use hyperon_atom::matcher::Bindings;
 
 use std::fmt::Debug;
 use std::borrow::Cow;
+use std::collections::HashMap;
 
 // TODO: should we duplicate structure for an owned and borrowed cases to eliminate Cow
 #[derive(PartialEq, Debug)]
@@ -146,6 +147,21 @@ impl<'a> Iterator for AtomIter<'a> {
 /// Iterator over results of the query to the index.
 pub type QueryResult = Box<dyn Iterator<Item=Bindings>>;
 
+/// Statistics of the [AtomIndex] content returned by [AtomIndex::stats].
+/// Head of the atom is the first symbol of the expression or the atom
+/// itself when it is a symbol. Atoms which have no head (variables, grounded
+/// atoms and expressions which start from a non-symbol atom) are not put
+/// into any bucket.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct IndexStats {
+    /// Number of atoms in the index including duplicates
+    pub atoms: usize,
+    /// Number of distinct heads of the atoms
+    pub distinct_heads: usize,
+    /// Maximal number of atoms which have the same head
+    pub max_bucket: usize,
+}
+
 /// Atom index implementation, parameterized by [DuplicationStrategy].
 #[derive(Default, Debug, Clone, PartialEq, Eq)]
 pub struct AtomIndex<D: DuplicationStrategy = NoDuplication> {
@@ -188,6 +204,15 @@ impl<D: DuplicationStrategy> AtomIndex<D> {
         Box::new(self.trie.query(key).into_iter())
     }
 
+    /// Query atoms which can be unified with `atom` from index spending
+    /// `budget`. When `budget` is exhausted index stops matching and returns
+    /// results found before, see [QueryBudget].
+    pub fn query_budgeted(&self, atom: &Atom, budget: &mut QueryBudget) -> QueryResult {
+        let key = AtomIter::from_ref(&atom)
+            .map(|token| Self::atom_token_to_query_index_key(token));
+        Box::new(self.trie.query_budgeted(key, budget).into_iter())
+    }
+
     fn atom_token_to_query_index_key<'a>(token: AtomToken<'a>) -> QueryKey<'a> {
         match token {
             AtomToken::StartExpr(Some(atom)) => QueryKey::StartExpr(atom),
@@ -204,15 +229,52 @@ impl<D: DuplicationStrategy> AtomIndex<D> {
         self.trie.remove(key)
     }
 
+    /// Returns [true] if index contains specific atom. Atom is matched by
+    /// equality, i.e. variables are not unified.
+    pub fn contains(&self, atom: &Atom) -> bool {
+        let key = AtomIter::from_ref(&atom)
+            .map(|token| Self::atom_token_to_query_index_key(token));
+        self.trie.contains(key)
+    }
+
     /// Iterate via atoms in index.
     pub fn iter(&self) -> Box<dyn Iterator<Item=Cow<'_, Atom>> + '_> {
        self.trie.unpack_atoms()
     }
 
+    /// Returns statistics of the index content. It is intended to diagnose
+    /// slow queries and it is linear in the number of atoms.
+    pub fn stats(&self) -> IndexStats {
+        let mut buckets: HashMap<SymbolAtom, usize> = HashMap::new();
+        for atom in self.iter() {
+            let head = match atom.as_ref() {
+                Atom::Symbol(sym) => Some(sym),
+                Atom::Expression(expr) => match expr.children().first() {
+                    Some(Atom::Symbol(sym)) => Some(sym),
+                    _ => None,
+                },
+                _ => None,
+            };
+            if let Some(head) = head {
+                *buckets.entry(head.clone()).or_insert(0) += 1;
+            }
+        }
+        IndexStats{
+            atoms: self.len(),
+            distinct_heads: buckets.len(),
+            max_bucket: buckets.values().copied().max().unwrap_or(0),
+        }
+    }
+
     /// Returns [true] if index has no atoms.
     pub fn is_empty(&self) -> bool {
         self.trie.is_empty()
     }
+
+    /// Returns number of atoms in index including duplicates.
+    pub fn len(&self) -> usize {
+        self.trie.len()
+    }
 }
 
 #[cfg(test)]
@@ -635,4 +697,32 @@ mod test {
         let actual: Vec<_> = index.query(&expr!("A" "B" "C")).collect();
         assert_eq_no_order!(actual, vec![bind!{ x: expr!("A" "B" "C") }]);
     }
+
+    #[test]
+    fn atom_index_query_budgeted_stops_matching() {
+        let mut index = AtomIndex::new();
+        for i in 0..100 {
+            index.insert(Atom::expr([sym!("A"), Atom::sym(format!("B{}", i))]));
+        }
+        let query = expr!("A" x);
+
+        let mut budget = QueryBudget::new(usize::MAX);
+        assert_eq!(index.query_budgeted(&query, &mut budget).count(), 100);
+        assert!(!budget.is_exhausted());
+        let spent = usize::MAX - budget.remaining();
+
+        let mut budget = QueryBudget::new(spent);
+        assert_eq!(index.query_budgeted(&query, &mut budget).count(), 100);
+        assert!(!budget.is_exhausted());
+
+        let mut budget = QueryBudget::new(spent / 10);
+        let found = index.query_budgeted(&query, &mut budget).count();
+        assert!(budget.is_exhausted());
+        assert_eq!(budget.remaining(), 0);
+        assert!(found > 0 && found < 20, "found: {}", found);
+
+        let mut budget = QueryBudget::new(0);
+        assert_eq!(index.query_budgeted(&query, &mut budget).count(), 0);
+        assert!(budget.is_exhausted());
+    }
 }
@@ -107,6 +107,44 @@ impl QueryKey<'_> {
 
 }
 
+/// Limits the work done by [AtomTrie::query_budgeted]. Each trie node
+/// visited and each atom of the trie unified with the query key spends one
+/// unit of the budget. When budget is exhausted trie stops matching the
+/// query. The same budget can be passed to a few queries to limit the work
+/// done by all of them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryBudget {
+    remaining: usize,
+    exhausted: bool,
+}
+
+impl QueryBudget {
+    /// New budget which allows spending `max_visited` units.
+    pub fn new(max_visited: usize) -> Self {
+        Self{ remaining: max_visited, exhausted: false }
+    }
+
+    /// Returns number of units which are not spent yet.
+    pub fn remaining(&self) -> usize {
+        self.remaining
+    }
+
+    /// Returns `true` if matching was stopped because the budget was exhausted.
+    pub fn is_exhausted(&self) -> bool {
+        self.exhausted
+    }
+
+    fn spend(&mut self) -> bool {
+        if self.remaining > 0 {
+            self.remaining -= 1;
+            true
+        } else {
+            self.exhausted = true;
+            false
+        }
+    }
+}
+
 /// Storage for the atoms from the [AtomTrie] which maps atoms to the [TrieKey] and
 /// back. It consists of two storages. One for storing hashable atoms which
 /// allows atoms extraction. Second for storing non-hashable atoms which doesn't
@@ -215,6 +253,7 @@ pub struct AtomTrie<D: DuplicationStrategy = NoDuplication>{
     nodes: HoleyVec<TrieNode>,
     index: HashMap<(NodeId, TrieKey), NodeId>,
     root: NodeId,
+    count: usize,
     _phantom: std::marker::PhantomData<D>,
 }
 
@@ -227,6 +266,7 @@ impl<D: DuplicationStrategy> Default for AtomTrie<D> {
             nodes,
             index: HashMap::new(),
             root,
+            count: 0,
             _phantom: Default::default(),
         }
     }
@@ -258,7 +298,12 @@ impl<D: DuplicationStrategy> AtomTrie<D> {
                     },
                 }
             },
-            None => D::add_atom(&mut self.nodes[node_id]),
+            None => {
+                let leaf = &mut self.nodes[node_id];
+                let prev = leaf.leaf_counter();
+                D::add_atom(leaf);
+                self.count += leaf.leaf_counter() - prev;
+            },
         }
     }
 
@@ -271,24 +316,37 @@ impl<D: DuplicationStrategy> AtomTrie<D> {
     /// Query trie using list of the [QueryKey] instances.
     #[inline]
     pub fn query<'a, I: Debug + Clone + Iterator<Item=QueryKey<'a>>>(&self, key: I) -> BindingsSet {
+        self.query_budgeted(key, &mut QueryBudget::new(usize::MAX))
+    }
+
+    /// Query trie using list of the [QueryKey] instances spending `budget`.
+    /// Matching stops when `budget` is exhausted and results found before
+    /// are returned, see [QueryBudget].
+    pub fn query_budgeted<'a, I>(&self, key: I, budget: &mut QueryBudget) -> BindingsSet
+        where I: Debug + Clone + Iterator<Item=QueryKey<'a>>
+    {
         let mut mapper = CachingMapper::new(VariableAtom::make_unique);
-        self.query_internal(self.root, key, &mut mapper)
+        self.query_internal(self.root, key, &mut mapper, budget)
     }
 
     // TODO: write an algorithm which returns an iterator instead of collected result
     fn query_internal<'a, I, M>(&self, node_id: NodeId, mut key: I,
-        mapper: &mut CachingMapper<VariableAtom, VariableAtom, M>) -> BindingsSet
+        mapper: &mut CachingMapper<VariableAtom, VariableAtom, M>,
+        budget: &mut QueryBudget) -> BindingsSet
         where
             I: Debug + Clone + Iterator<Item=QueryKey<'a>>,
             M: Fn(VariableAtom)->VariableAtom
     {
+        if !budget.spend() {
+            return BindingsSet::empty();
+        }
         match key.next() {
             Some(head) => {
                 match self.keys.query_key(&head) {
                     (AtomMatchMode::Equality, head, atom) =>
-                        self.match_key_by_equality(node_id, atom, head, key, mapper),
+                        self.match_key_by_equality(node_id, atom, head, key, mapper, budget),
                     (AtomMatchMode::Unification, _head, Some(atom)) =>
-                        self.match_key_by_unification(node_id, atom, key, mapper),
+                        self.match_key_by_unification(node_id, atom, key, mapper, budget),
                     (AtomMatchMode::Unification, _head, None) => unreachable!(),
                 }
             },
@@ -300,7 +358,8 @@ impl<D: DuplicationStrategy> AtomTrie<D> {
 
     fn match_key_by_equality<'a, I, M>(&self, node_id: NodeId,
         atom: Option<&'a Atom>, key: Option<TrieKey>, mut tail: I,
-        mapper: &mut CachingMapper<VariableAtom, VariableAtom, M>) -> BindingsSet
+        mapper: &mut CachingMapper<VariableAtom, VariableAtom, M>,
+        budget: &mut QueryBudget) -> BindingsSet
         where
             I: Debug + Clone + Iterator<Item=QueryKey<'a>>,
             M: Fn(VariableAtom)->VariableAtom,
@@ -315,7 +374,7 @@ impl<D: DuplicationStrategy> AtomTrie<D> {
             // match equality hashable key
             is_start_expr = equality_key == TK_START_EXPR;
             if let Some(&child_id) = self.index.get(&(node_id, equality_key)) {
-                result.extend(self.query_internal(child_id, tail.clone(), mapper))
+                result.extend(self.query_internal(child_id, tail.clone(), mapper, budget))
             }
         } else {
             // match equality nonhashable key (see TrieKeyStorage::add_atom)
@@ -326,7 +385,7 @@ impl<D: DuplicationStrategy> AtomTrie<D> {
                 for (entry, key) in it {
                     if entry == query {
                         let child_id = *self.index.get(&(node_id, key)).unwrap();
-                        result.extend(self.query_internal(child_id, tail.clone(), mapper));
+                        result.extend(self.query_internal(child_id, tail.clone(), mapper, budget));
                     }
                 }
             }
@@ -344,7 +403,10 @@ impl<D: DuplicationStrategy> AtomTrie<D> {
                     (entry, child_id)
                 });
             for (entry, child_id) in it {
-                let mut unify_res = self.unify_entry(entry, query, child_id, tail.clone(), mapper);
+                if budget.is_exhausted() {
+                    break;
+                }
+                let mut unify_res = self.unify_entry(entry, query, child_id, tail.clone(), mapper, budget);
                 result.extend(unify_res.drain(..));
             }
         }
@@ -352,12 +414,16 @@ impl<D: DuplicationStrategy> AtomTrie<D> {
     }
 
     fn unify_entry<'a, I, M>(&self, entry: &Atom, key: &Atom, child_id: NodeId, tail: I,
-        mapper: &mut CachingMapper<VariableAtom, VariableAtom, M>) -> BindingsSet
+        mapper: &mut CachingMapper<VariableAtom, VariableAtom, M>,
+        budget: &mut QueryBudget) -> BindingsSet
         where
             I: Debug + Clone + Iterator<Item=QueryKey<'a>>,
             M: Fn(VariableAtom)->VariableAtom
     {
         let mut result = BindingsSet::empty();
+        if !budget.spend() {
+            return result;
+        }
         let mut entry = entry.clone();
         // TODO: replacing variables each time could be eliminated
         entry.iter_mut().filter_type::<&mut VariableAtom>().for_each(|var| *var = mapper.replace(var.clone()));
@@ -366,7 +432,7 @@ impl<D: DuplicationStrategy> AtomTrie<D> {
         if result.is_empty() {
             result
         } else {
-            let tail_result = self.query_internal(child_id, tail, mapper);
+            let tail_result = self.query_internal(child_id, tail, mapper, budget);
             // TODO: we could move BindingsSet into merge instead of passing by reference
             result.merge(&tail_result).into_iter().filter(|b| !b.has_loops()).collect()
         }
@@ -374,14 +440,18 @@ impl<D: DuplicationStrategy> AtomTrie<D> {
 
     fn match_key_by_unification<'a, I, M>(&self, node_id: NodeId,
         atom: &Atom, tail: I,
-        mapper: &mut CachingMapper<VariableAtom, VariableAtom, M>) -> BindingsSet
+        mapper: &mut CachingMapper<VariableAtom, VariableAtom, M>,
+        budget: &mut QueryBudget) -> BindingsSet
         where
             I: Debug + Clone + Iterator<Item=QueryKey<'a>>,
             M: Fn(VariableAtom)->VariableAtom
     {
         let mut result = BindingsSet::empty();
         for (entry, child_id) in self.unpack_atoms_internal(node_id) {
-            let mut tail_result = self.unify_entry(&entry, atom, child_id, tail.clone(), mapper);
+            if budget.is_exhausted() {
+                break;
+            }
+            let mut tail_result = self.unify_entry(&entry, atom, child_id, tail.clone(), mapper, budget);
             result.extend(tail_result.drain(..));
         }
         result
@@ -454,17 +524,53 @@ impl<D: DuplicationStrategy> AtomTrie<D> {
                 }
             },
             None => {
-                D::remove_atom(&mut self.nodes[node_id]);
+                let leaf = &mut self.nodes[node_id];
+                let prev = leaf.leaf_counter();
+                D::remove_atom(leaf);
+                self.count -= prev - leaf.leaf_counter();
                 true
             },
         }
     }
 
+    /// Return `true` if trie contains specific list of [QueryKey]. Each key
+    /// is matched by equality.
+    #[inline]
+    pub fn contains<'a, I: Iterator<Item=QueryKey<'a>>>(&self, key: I) -> bool {
+        self.contains_internal(self.root, key)
+    }
+
+    fn contains_internal<'a, I>(&self, node_id: NodeId, mut key: I) -> bool
+        where I: Iterator<Item=QueryKey<'a>>
+    {
+        match key.next() {
+            Some(head) => {
+                let child_id = match self.keys.query_key(&head) {
+                    (_match, None, None) => None,
+                    (_match, Some(key), _atom) => self.index.get(&(node_id, key)).copied(),
+                    (match_mode, None, Some(atom)) => {
+                        self.nodes[node_id].iter_match(match_mode)
+                            .find(|(_i, k)| atom == unsafe{ self.keys.get_atom_unchecked(*k) })
+                            .map(|(_i, k)| *self.index.get(&(node_id, k)).unwrap())
+                    },
+                };
+                child_id.is_some_and(|child_id| self.contains_internal(child_id, key))
+            },
+            None => self.nodes[node_id].leaf_counter() > 0,
+        }
+    }
+
     /// Return `true` if trie is empty.
     #[inline]
     pub fn is_empty(&self) -> bool {
         self.nodes[self.root].is_leaf()
     }
+
+    /// Returns number of atoms in the trie including duplicates. Number is
+    /// updated on each insert and remove thus it is returned in constant time.
+    pub fn len(&self) -> usize {
+        self.count
+    }
 }
 
 /// Which storage keeps the value of the key.
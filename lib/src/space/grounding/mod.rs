@@ -4,13 +4,79 @@ pub mod index;
 
 use super::*;
 use hyperon_atom::*;
+use crate::metta::EQUAL_SYMBOL;
+use crate::metta::text::{SExprParser, Tokenizer};
 
 use std::fmt::Debug;
 use std::collections::HashSet;
+use hyperon_common::collections::ListMap;
 use index::*;
 
 pub use index::{ALLOW_DUPLICATION, NO_DUPLICATION};
 
+// Atom constraints
+
+/// Error returned when atom violates [AtomConstraints].
+#[derive(Debug, Clone, PartialEq)]
+pub enum AtomError {
+    /// Atom is or contains an empty expression.
+    EmptyExpression,
+    /// Nesting depth of the atom exceeds the limit.
+    TooDeep {
+        /// Maximal allowed depth
+        max_depth: usize,
+    },
+}
+
+impl Display for AtomError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AtomError::EmptyExpression => write!(f, "Atom contains an empty expression"),
+            AtomError::TooDeep{ max_depth } => write!(f, "Atom nesting depth exceeds {}", max_depth),
+        }
+    }
+}
+
+/// Structural constraints which are checked by [GroundingSpace::try_add].
+/// Depth of a symbol, variable or grounded atom is 0, depth of an expression
+/// is its maximal child depth plus one.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AtomConstraints {
+    /// Maximal depth of the atom, `None` means depth is not limited.
+    pub max_depth: Option<usize>,
+    /// Whether atom is allowed to contain empty expressions.
+    pub allow_empty_expr: bool,
+}
+
+impl AtomConstraints {
+    /// Checks `atom` against constraints.
+    pub fn check(&self, atom: &Atom) -> Result<(), AtomError> {
+        self.check_depth(atom, 0)
+    }
+
+    fn check_depth(&self, atom: &Atom, depth: usize) -> Result<(), AtomError> {
+        match atom {
+            Atom::Expression(expr) => {
+                if !self.allow_empty_expr && expr.children().is_empty() {
+                    return Err(AtomError::EmptyExpression);
+                }
+                let depth = depth + 1;
+                match self.max_depth {
+                    Some(max_depth) if depth > max_depth =>
+                        Err(AtomError::TooDeep{ max_depth }),
+                    _ => expr.children().iter()
+                        .try_for_each(|child| self.check_depth(child, depth)),
+                }
+            },
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Maximal number of passes made by [GroundingSpace::apply_rules] over the
+/// space. It bounds the rewriting when rules don't reach a fixpoint.
+pub const MAX_REWRITE_ITERATIONS: usize = 64;
+
 // Grounding space
 
 /// In-memory space which can contain grounded atoms.
@@ -20,6 +86,7 @@ pub struct GroundingSpace<D: DuplicationStrategy = AllowDuplication> {
     index: AtomIndex<D>,
     common: SpaceCommon,
     name: Option<String>,
+    constraints: AtomConstraints,
 }
 
 impl GroundingSpace {
@@ -30,15 +97,60 @@ impl GroundingSpace {
 
     /// Constructs space from vector of atoms.
     pub fn from_vec(atoms: Vec<Atom>) -> Self {
-        let mut index = AtomIndex::with_strategy(ALLOW_DUPLICATION);
-        for atom in atoms {
-            index.insert(atom);
-        }
-        Self{
-            index,
-            common: SpaceCommon::default(),
-            name: None,
+        Self::from_vec_with_strategy(atoms, ALLOW_DUPLICATION)
+    }
+
+    /// Constructs space from S-expression `text` parsing atoms using the
+    /// `tokenizer`. Each atom is added into the space, thus duplicated atoms
+    /// are kept. Returns parser error message if text cannot be parsed. It
+    /// is an inverse of the [GroundingSpace::to_sexpr].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hyperon_atom::{expr, sym};
+    /// use hyperon::metta::text::Tokenizer;
+    /// use hyperon::space::grounding::GroundingSpace;
+    ///
+    /// let space = GroundingSpace::from_sexpr("A (B C)", &Tokenizer::new()).unwrap();
+    ///
+    /// assert_eq!(space.atoms(), vec![sym!("A"), expr!("B" "C")]);
+    /// assert!(GroundingSpace::from_sexpr("(A", &Tokenizer::new()).is_err());
+    /// ```
+    pub fn from_sexpr(text: &str, tokenizer: &Tokenizer) -> Result<Self, String> {
+        let mut parser = SExprParser::new(text);
+        let mut atoms = Vec::new();
+        while let Some(atom) = parser.parse(tokenizer)? {
+            atoms.push(atom);
         }
+        Ok(Self::from_vec(atoms))
+    }
+
+    /// Constructs space of binary relation atoms from the adjacency map.
+    /// Each edge `src -> dst` becomes an `(relation src dst)` atom.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hyperon_atom::{expr, sym};
+    /// use hyperon_atom::matcher::BindingsSet;
+    /// use hyperon_common::collections::ListMap;
+    /// use hyperon::space::grounding::GroundingSpace;
+    ///
+    /// let mut adj = ListMap::new();
+    /// adj.insert(sym!("A"), vec![sym!("B"), sym!("C")]);
+    ///
+    /// let space = GroundingSpace::from_adjacency("edge", &adj);
+    ///
+    /// assert_eq!(space.query(&expr!("edge" "A" "C")), BindingsSet::single());
+    /// ```
+    pub fn from_adjacency(relation: &str, adj: &ListMap<Atom, Vec<Atom>>) -> Self {
+        let relation = Atom::sym(relation);
+        let atoms = adj.iter()
+            .flat_map(|(src, dsts)| dsts.iter()
+                .map(|dst| Atom::expr([relation.clone(), src.clone(), dst.clone()])))
+            .collect();
+        Self::from_vec(atoms)
     }
 }
 
@@ -49,6 +161,33 @@ impl<D: DuplicationStrategy> GroundingSpace<D> {
             index: AtomIndex::with_strategy(strategy),
             common: SpaceCommon::default(),
             name: None,
+            constraints: AtomConstraints::default(),
+        }
+    }
+
+    /// Constructs space from vector of atoms using duplication strategy.
+    /// When strategy is [NoDuplication] repeated atoms are kept once.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hyperon_atom::sym;
+    /// use hyperon::space::grounding::{GroundingSpace, NO_DUPLICATION};
+    ///
+    /// let space = GroundingSpace::from_vec_with_strategy(vec![sym!("A"), sym!("A")], NO_DUPLICATION);
+    ///
+    /// assert_eq!(space.atoms(), vec![sym!("A")]);
+    /// ```
+    pub fn from_vec_with_strategy(atoms: Vec<Atom>, strategy: D) -> Self {
+        let mut index = AtomIndex::with_strategy(strategy);
+        for atom in atoms {
+            index.insert(atom);
+        }
+        Self{
+            index,
+            common: SpaceCommon::default(),
+            name: None,
+            constraints: AtomConstraints::default(),
         }
     }
 
@@ -75,6 +214,95 @@ impl<D: DuplicationStrategy> GroundingSpace<D> {
         self.common.notify_all_observers(&SpaceEvent::Add(atom));
     }
 
+    /// Adds all `atoms` into space. Observers are notified by a single
+    /// [SpaceEvent::BatchAdd] event which contains all added atoms.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hyperon_atom::sym;
+    /// use hyperon_atom::matcher::BindingsSet;
+    /// use hyperon::space::grounding::GroundingSpace;
+    ///
+    /// let mut space = GroundingSpace::new();
+    ///
+    /// space.add_all(vec![sym!("A"), sym!("B")]);
+    ///
+    /// assert_eq!(space.query(&sym!("A")), BindingsSet::single());
+    /// assert_eq!(space.query(&sym!("B")), BindingsSet::single());
+    /// ```
+    pub fn add_all(&mut self, atoms: impl IntoIterator<Item=Atom>) {
+        let atoms: Vec<Atom> = atoms.into_iter().collect();
+        log::debug!("GroundingSpace::add_all: {}, atoms: {}", self, atoms.len());
+        if atoms.is_empty() {
+            return;
+        }
+        for atom in &atoms {
+            self.index.insert(atom.clone());
+        }
+        self.common.notify_all_observers(&SpaceEvent::BatchAdd(atoms));
+    }
+
+    /// Adds all atoms of the `other` space into this space. Observers of this
+    /// space are notified by a single [SpaceEvent::BatchAdd] event as in
+    /// [GroundingSpace::add_all]. Atoms are inserted using the duplication
+    /// strategy of this space: when it is [AllowDuplication] atoms which are
+    /// present in both spaces become duplicated, when it is [NoDuplication]
+    /// they are kept once independently of the `other` space strategy.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hyperon_atom::sym;
+    /// use hyperon_atom::matcher::BindingsSet;
+    /// use hyperon::space::grounding::GroundingSpace;
+    ///
+    /// let mut space = GroundingSpace::from_vec(vec![sym!("A")]);
+    /// let other = GroundingSpace::from_vec(vec![sym!("B")]);
+    ///
+    /// space.merge_from(&other);
+    ///
+    /// assert_eq!(space.query(&sym!("A")), BindingsSet::single());
+    /// assert_eq!(space.query(&sym!("B")), BindingsSet::single());
+    /// ```
+    pub fn merge_from<O: DuplicationStrategy>(&mut self, other: &GroundingSpace<O>) {
+        log::debug!("GroundingSpace::merge_from: {}, other: {}", self, other);
+        self.add_all(other.atoms());
+    }
+
+    /// Checks `atom` against space [AtomConstraints] and adds it into space.
+    /// Returns [AtomError] and doesn't add atom if the check fails. Unlike
+    /// [GroundingSpace::add] it is intended to be used with untrusted input.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hyperon_atom::{expr, sym};
+    /// use hyperon_atom::matcher::BindingsSet;
+    /// use hyperon::space::grounding::{GroundingSpace, AtomError};
+    ///
+    /// let mut space = GroundingSpace::new();
+    ///
+    /// assert_eq!(space.try_add(expr!("A" "B")), Ok(()));
+    /// assert_eq!(space.try_add(expr!("A" ())), Err(AtomError::EmptyExpression));
+    /// assert_eq!(space.query(&expr!("A" x)).len(), 1);
+    /// ```
+    pub fn try_add(&mut self, atom: Atom) -> Result<(), AtomError> {
+        self.constraints.check(&atom)?;
+        self.add(atom);
+        Ok(())
+    }
+
+    /// Sets constraints which are checked by [GroundingSpace::try_add].
+    pub fn set_constraints(&mut self, constraints: AtomConstraints) {
+        self.constraints = constraints;
+    }
+
+    /// Returns constraints which are checked by [GroundingSpace::try_add].
+    pub fn constraints(&self) -> &AtomConstraints {
+        &self.constraints
+    }
+
     /// Removes `atom` from space. Returns true if atom was found and removed,
     /// and false otherwise.
     ///
@@ -100,9 +328,39 @@ impl<D: DuplicationStrategy> GroundingSpace<D> {
         is_removed
     }
 
+    /// Removes each of the `atoms` from space and returns the number of atoms
+    /// removed. Observers are notified by a single [SpaceEvent::BatchRemove]
+    /// event which contains only atoms which were found and removed. No
+    /// event is sent when nothing is removed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hyperon_atom::sym;
+    /// use hyperon::space::grounding::GroundingSpace;
+    ///
+    /// let mut space = GroundingSpace::from_vec(vec![sym!("A"), sym!("B"), sym!("C")]);
+    ///
+    /// assert_eq!(space.remove_all(&[sym!("A"), sym!("C"), sym!("D")]), 2);
+    /// assert_eq!(space.atoms(), vec![sym!("B")]);
+    /// ```
+    pub fn remove_all(&mut self, atoms: &[Atom]) -> usize {
+        log::debug!("GroundingSpace::remove_all: {}, atoms: {}", self, atoms.len());
+        let removed: Vec<Atom> = atoms.iter()
+            .filter(|atom| self.index.remove(atom))
+            .cloned()
+            .collect();
+        let count = removed.len();
+        if count > 0 {
+            self.common.notify_all_observers(&SpaceEvent::BatchRemove(removed));
+        }
+        count
+    }
+
     /// Replaces `from` atom to `to` atom inside space. Doesn't add `to` when
     /// `from` is not found. Returns true if atom was found and replaced, and
-    /// false otherwise.
+    /// false otherwise. When `from` is equal to `to` the space is not changed
+    /// and no event is sent.
     ///
     /// # Examples
     ///
@@ -119,6 +377,9 @@ impl<D: DuplicationStrategy> GroundingSpace<D> {
     /// assert_eq!(space.query(&sym!("B")), BindingsSet::single());
     /// ```
     pub fn replace(&mut self, from: &Atom, to: Atom) -> bool {
+        if *from == to {
+            return self.index.contains(from);
+        }
         let is_replaced = self.index.remove(from);
         if is_replaced {
             self.index.insert(to.clone());
@@ -127,6 +388,172 @@ impl<D: DuplicationStrategy> GroundingSpace<D> {
         is_replaced
     }
 
+    /// Removes all atoms from space. Observers keep being registered and are
+    /// notified by a separate [SpaceEvent::Remove] event for each removed
+    /// atom including duplicates.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hyperon_atom::sym;
+    /// use hyperon::space::Space;
+    /// use hyperon::space::grounding::GroundingSpace;
+    ///
+    /// let mut space = GroundingSpace::from_vec(vec![sym!("A"), sym!("B")]);
+    ///
+    /// space.clear();
+    ///
+    /// assert_eq!(space.atom_count(), Some(0));
+    /// ```
+    pub fn clear(&mut self) {
+        log::debug!("GroundingSpace::clear: {}", self);
+        let atoms: Vec<Atom> = self.index.iter().map(|a| a.into_owned()).collect();
+        self.index = AtomIndex::default();
+        for atom in atoms {
+            self.common.notify_all_observers(&SpaceEvent::Remove(atom));
+        }
+    }
+
+    /// Keeps only atoms for which `f` returns true and removes all others.
+    /// Observers are notified by a separate [SpaceEvent::Remove] event for
+    /// each removed atom. Duplicated atoms are checked and removed
+    /// individually.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hyperon_atom::{expr, sym};
+    /// use hyperon::space::grounding::GroundingSpace;
+    ///
+    /// let mut space = GroundingSpace::from_vec(vec![sym!("A"), expr!("B" "C")]);
+    ///
+    /// space.retain(|atom| atom == &sym!("A"));
+    ///
+    /// assert_eq!(space.atoms(), vec![sym!("A")]);
+    /// ```
+    pub fn retain(&mut self, mut f: impl FnMut(&Atom) -> bool) {
+        log::debug!("GroundingSpace::retain: {}", self);
+        let removed: Vec<Atom> = self.index.iter()
+            .filter(|atom| !f(atom))
+            .map(|atom| atom.into_owned())
+            .collect();
+        for atom in removed {
+            self.index.remove(&atom);
+            self.common.notify_all_observers(&SpaceEvent::Remove(atom));
+        }
+    }
+
+    /// Returns distinct structural shapes of the atoms of the space. Shape of
+    /// an atom is the atom in which each leaf value is replaced by the `_`
+    /// symbol except symbols which are heads of expressions, for example
+    /// shape of `(edge A (B C))` is `(edge _ (B _))`. Atom doesn't implement
+    /// [std::hash::Hash] thus shapes are returned as a vector in order of
+    /// their first appearance. Shapes are deduplicated using their hashable
+    /// structural copy which keeps the method linear in the number of atoms.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hyperon_atom::expr;
+    /// use hyperon::space::grounding::GroundingSpace;
+    ///
+    /// let space = GroundingSpace::from_vec(vec![expr!("edge" "A" "B"), expr!("edge" "B" "C")]);
+    ///
+    /// assert_eq!(space.shapes(), vec![expr!("edge" "_" "_")]);
+    /// ```
+    pub fn shapes(&self) -> Vec<Atom> {
+        let mut seen = HashSet::new();
+        let mut shapes = Vec::new();
+        for atom in self.index.iter() {
+            let shape = atom_shape(&atom);
+            if seen.insert(ShapeKey::new(&shape)) {
+                shapes.push(shape);
+            }
+        }
+        shapes
+    }
+
+    /// Rewrites atoms of the space using `(= <lhs> <rhs>)` rules from the
+    /// `rules` space. On each pass each atom which matches the `<lhs>` of some
+    /// rule is replaced by the `<rhs>` with bindings applied, the first
+    /// matching rule is used. Passes are repeated until no atom is rewritten
+    /// or [MAX_REWRITE_ITERATIONS] passes are made. Returns the number of
+    /// rewrites made. Atoms of the `rules` space which are not rules are
+    /// ignored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hyperon_atom::{expr, sym};
+    /// use hyperon::space::grounding::GroundingSpace;
+    ///
+    /// let mut space = GroundingSpace::from_vec(vec![expr!("parent" "Tom" "Bob")]);
+    /// let rules = GroundingSpace::from_vec(vec![expr!("=" ("parent" x y) ("child" y x))]);
+    ///
+    /// assert_eq!(space.apply_rules(&rules), 1);
+    /// assert_eq!(space.atoms(), vec![expr!("child" "Bob" "Tom")]);
+    /// ```
+    pub fn apply_rules<R: DuplicationStrategy>(&mut self, rules: &GroundingSpace<R>) -> usize {
+        log::debug!("GroundingSpace::apply_rules: {}, rules: {}", self, rules);
+        let rules: Vec<Atom> = rules.iter()
+            .filter(|rule| rewrite_rule(rule).is_some())
+            .map(|rule| rule.into_owned())
+            .collect();
+        let mut count = 0;
+        for _ in 0..MAX_REWRITE_ITERATIONS {
+            let rewrites: Vec<(Atom, Atom)> = self.index.iter()
+                .filter_map(|atom| rewrite_atom(&rules, &atom)
+                    .map(|to| (atom.into_owned(), to)))
+                .collect();
+            if rewrites.is_empty() {
+                break;
+            }
+            for (from, to) in rewrites {
+                if self.replace(&from, to) {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    /// Returns true if space has no atoms.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hyperon_atom::sym;
+    /// use hyperon::space::grounding::GroundingSpace;
+    ///
+    /// let mut space = GroundingSpace::new();
+    /// assert!(space.is_empty());
+    ///
+    /// space.add(sym!("A"));
+    /// assert!(!space.is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Returns true if space contains exactly the `atom`. Unlike
+    /// [GroundingSpace::query] atoms are compared structurally and variables
+    /// are not unified.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hyperon_atom::{expr, sym};
+    /// use hyperon::space::grounding::GroundingSpace;
+    ///
+    /// let space = GroundingSpace::from_vec(vec![expr!("A" "B")]);
+    ///
+    /// assert!(space.contains(&expr!("A" "B")));
+    /// assert!(!space.contains(&expr!("A" x)));
+    /// ```
+    pub fn contains(&self, atom: &Atom) -> bool {
+        self.index.contains(atom)
+    }
+
     /// Executes `query` on the space and returns variable bindings found.
     /// Query may include sub-queries glued by [COMMA_SYMBOL] symbol.
     /// Each [Bindings](matcher::Bindings) instance in the returned [BindingsSet]
@@ -150,18 +577,297 @@ impl<D: DuplicationStrategy> GroundingSpace<D> {
         complex_query(query, |query| self.single_query(query))
     }
 
+    /// Executes `query` on the space after applying `initial` bindings to it
+    /// and merges each result with `initial`. It is the same step which is
+    /// made for each sub-query glued by [COMMA_SYMBOL] symbol in
+    /// [GroundingSpace::query]. Results which are inconsistent with `initial`
+    /// are dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hyperon_atom::{expr, bind, bind_set, sym};
+    /// use hyperon::space::grounding::GroundingSpace;
+    ///
+    /// let space = GroundingSpace::from_vec(vec![expr!("A" "B" "C"), expr!("A" "D" "E")]);
+    ///
+    /// let result = space.query_with_bindings(&expr!("A" x y), &bind!{x: sym!("B")});
+    ///
+    /// assert_eq!(result, bind_set![{x: sym!("B"), y: sym!("C")}]);
+    /// ```
+    pub fn query_with_bindings(&self, query: &Atom, initial: &Bindings) -> BindingsSet {
+        let query = matcher::apply_bindings_to_atom_move(query.clone(), initial);
+        self.query(&query).into_iter()
+            .flat_map(|next| next.merge(initial))
+            .collect()
+    }
+
+    /// Executes `query` on the space and returns not more than `limit`
+    /// variable bindings found. Returned bindings are the first `limit`
+    /// bindings returned by [GroundingSpace::query]. When query contains
+    /// sub-queries glued by [COMMA_SYMBOL] symbol the limit is applied to
+    /// the final results.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hyperon_atom::expr;
+    /// use hyperon::space::grounding::GroundingSpace;
+    ///
+    /// let space = GroundingSpace::from_vec(vec![expr!("A" "B"), expr!("A" "C"), expr!("A" "D")]);
+    ///
+    /// let result = space.query_limited(&expr!("A" x), 2);
+    ///
+    /// assert_eq!(result.len(), 2);
+    /// ```
+    pub fn query_limited(&self, query: &Atom, limit: usize) -> BindingsSet {
+        complex_query_limited(query, limit, |query| Box::new(self.single_query_iter(query)))
+    }
+
+    /// Executes `query` on the space and returns the `query` with bindings of
+    /// each result applied. For the query without sub-queries it is the
+    /// matched atom. Variables which are not bound by the result are kept.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hyperon_atom::expr;
+    /// use hyperon::space::grounding::GroundingSpace;
+    ///
+    /// let space = GroundingSpace::from_vec(vec![expr!("A" "B"), expr!("C" "D")]);
+    ///
+    /// assert_eq!(space.query_atoms(&expr!("A" x)), vec![expr!("A" "B")]);
+    /// ```
+    pub fn query_atoms(&self, query: &Atom) -> Vec<Atom> {
+        self.query(query).into_iter()
+            .map(|bindings| matcher::apply_bindings_to_atom_move(query.clone(), &bindings))
+            .collect()
+    }
+
+    /// Executes `query` on the space spending not more than `max_visited`
+    /// units of work. Each node of the index visited and each atom of the
+    /// index unified with the query spends one unit, see [QueryBudget]. The
+    /// budget is shared by all sub-queries glued by [COMMA_SYMBOL] symbol.
+    /// When budget is exhausted index stops matching and the results found
+    /// before are returned. Returns the bindings found and `true` if the query
+    /// was truncated because the budget was exhausted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hyperon_atom::expr;
+    /// use hyperon::space::grounding::GroundingSpace;
+    ///
+    /// let space = GroundingSpace::from_vec(vec![expr!("A" "B"), expr!("A" "C"), expr!("A" "D")]);
+    ///
+    /// let (result, truncated) = space.query_budgeted(&expr!("A" x), 1000);
+    /// assert_eq!((result.len(), truncated), (3, false));
+    ///
+    /// let (result, truncated) = space.query_budgeted(&expr!("A" x), 5);
+    /// assert!(truncated && result.len() < 3);
+    /// ```
+    pub fn query_budgeted(&self, query: &Atom, max_visited: usize) -> (BindingsSet, bool) {
+        let budget = RefCell::new(QueryBudget::new(max_visited));
+        let result = complex_query_limited(query, usize::MAX, |query| {
+            let results = self.index.query_budgeted(query, &mut budget.borrow_mut());
+            Box::new(Self::narrow_results(query, results))
+        });
+        let budget = budget.into_inner();
+        log::debug!("GroundingSpace::query_budgeted: {} remaining: {}, exhausted: {}",
+            self, budget.remaining(), budget.is_exhausted());
+        (result, budget.is_exhausted())
+    }
+
+    /// Executes `query` on the space and returns the first bindings found or
+    /// `None` if there are no results. As in [GroundingSpace::query_limited]
+    /// only the first result is narrowed and merged with the results of the
+    /// next sub-queries. Index still finds all matches of each sub-query
+    /// executed, thus it is not faster than [GroundingSpace::query] when a
+    /// simple query has a lot of matches.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hyperon_atom::{expr, bind, sym};
+    /// use hyperon::space::grounding::GroundingSpace;
+    ///
+    /// let space = GroundingSpace::from_vec(vec![expr!("A" "B"), expr!("B" "C")]);
+    ///
+    /// assert_eq!(space.query_first(&expr!("," ("A" x) (x y))), Some(bind!{x: sym!("B"), y: sym!("C")}));
+    /// assert_eq!(space.query_first(&expr!("C" x)), None);
+    /// ```
+    pub fn query_first(&self, query: &Atom) -> Option<Bindings> {
+        self.query_limited(query, 1).into_iter().next()
+    }
+
+    /// Executes `query` on the space and returns results sorted in ascending
+    /// order by the value of the `var` variable. Values are compared by their
+    /// text representation. Results in which `var` has no value are placed
+    /// at the end. See [GroundingSpace::query_sorted_by_cmp] to provide a
+    /// custom comparator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hyperon_atom::{expr, sym, VariableAtom};
+    /// use hyperon::space::grounding::GroundingSpace;
+    ///
+    /// let space = GroundingSpace::from_vec(vec![expr!("A" "c"), expr!("A" "a"), expr!("A" "b")]);
+    /// let x = VariableAtom::new("x");
+    ///
+    /// let result: Vec<_> = space.query_sorted_by(&expr!("A" x), &x).iter()
+    ///     .map(|b| b.resolve(&x).unwrap()).collect();
+    ///
+    /// assert_eq!(result, vec![sym!("a"), sym!("b"), sym!("c")]);
+    /// ```
+    pub fn query_sorted_by(&self, query: &Atom, var: &VariableAtom) -> Vec<Bindings> {
+        self.query_sorted_by_cmp(query, var, |a, b| a.to_string().cmp(&b.to_string()))
+    }
+
+    /// Same as [GroundingSpace::query_sorted_by] but values of the `var`
+    /// variable are compared using `cmp` comparator. It allows sorting
+    /// grounded values, for example numbers, by their values.
+    pub fn query_sorted_by_cmp<C>(&self, query: &Atom, var: &VariableAtom, mut cmp: C) -> Vec<Bindings>
+    where
+        C: FnMut(&Atom, &Atom) -> std::cmp::Ordering,
+    {
+        let mut results: Vec<(Option<Atom>, Bindings)> = self.query(query).into_iter()
+            .map(|bindings| (bindings.resolve(var), bindings))
+            .collect();
+        results.sort_by(|(a, _), (b, _)| match (a, b) {
+            (Some(a), Some(b)) => cmp(a, b),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => std::cmp::Ordering::Equal,
+        });
+        results.into_iter().map(|(_, bindings)| bindings).collect()
+    }
+
+    /// Returns number of results of the `query` on the space. The result is
+    /// equal to `query(query).len()`. The index still builds all bindings of
+    /// the simple query, only narrowing them to the query variables and
+    /// collecting them into [BindingsSet] is skipped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hyperon_atom::expr;
+    /// use hyperon::space::grounding::GroundingSpace;
+    ///
+    /// let space = GroundingSpace::from_vec(vec![expr!("A" "B"), expr!("A" "C"), expr!("D" "E")]);
+    ///
+    /// assert_eq!(space.count_matches(&expr!("A" x)), 2);
+    /// ```
+    pub fn count_matches(&self, query: &Atom) -> usize {
+        complex_query_count(query, |query| Box::new(self.single_query_iter(query)),
+            |query| self.index.query(query).count())
+    }
+
+    /// Executes `query` on the space and returns the `query` with bindings
+    /// applied for the results which are found using the most specific atoms
+    /// of the space. Specificity of an atom is measured by a number of
+    /// distinct variables it contains: the less variables the more specific
+    /// atom is. It can be used to select the most specific rule among the
+    /// applicable ones.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hyperon_atom::expr;
+    /// use hyperon::space::grounding::GroundingSpace;
+    ///
+    /// let space = GroundingSpace::from_vec(vec![
+    ///     expr!("=" ("f" x) "general"),
+    ///     expr!("=" ("f" "a") "specific"),
+    /// ]);
+    ///
+    /// let result = space.query_most_specific(&expr!("=" ("f" "a") r));
+    ///
+    /// assert_eq!(result, vec![expr!("=" ("f" "a") "specific")]);
+    /// ```
+    pub fn query_most_specific(&self, query: &Atom) -> Vec<Atom> {
+        let query_vars: HashSet<&VariableAtom> = query.iter().filter_type::<&VariableAtom>().collect();
+        let mut min_vars = usize::MAX;
+        let mut result = Vec::new();
+        for bindings in self.index.query(query) {
+            // Index renames variables of the matched atom, thus variables of
+            // the bindings which are not in the query belong to the atom
+            let mut vars: HashSet<VariableAtom> = bindings.vars().cloned().collect();
+            for (_var, value) in bindings.iter() {
+                vars.extend(value.iter().filter_type::<&VariableAtom>().cloned());
+            }
+            let vars = vars.iter().filter(|var| !query_vars.contains(var)).count();
+            if vars < min_vars {
+                min_vars = vars;
+                result.clear();
+            }
+            if vars == min_vars {
+                let bindings = bindings.narrow_vars(&query_vars);
+                result.push(matcher::apply_bindings_to_atom_move(query.clone(), &bindings));
+            }
+        }
+        result
+    }
+
     /// Executes simple `query` without sub-queries on the space.
     fn single_query(&self, query: &Atom) -> BindingsSet {
         log::debug!("GroundingSpace::single_query: {} query: {}", self, query);
-        let mut result = BindingsSet::empty();
+        let result: BindingsSet = self.single_query_iter(query).collect();
+        log::debug!("GroundinSpace::single_query: {} result: {}", self, result);
+        result
+    }
+
+    /// Executes simple `query` without sub-queries on the space. Bindings
+    /// found are narrowed lazily, thus when only part of the results is
+    /// consumed the rest of them is not processed.
+    fn single_query_iter<'a>(&self, query: &'a Atom) -> impl Iterator<Item=Bindings> + 'a {
+        Self::narrow_results(query, self.index.query(query))
+    }
+
+    /// Narrows `results` of the simple `query` to the variables of the query.
+    fn narrow_results<'a>(query: &'a Atom, results: QueryResult) -> impl Iterator<Item=Bindings> + 'a {
         let query_vars: HashSet<&VariableAtom> = query.iter().filter_type::<&VariableAtom>().collect();
-        for bindings in self.index.query(query) {
+        results.map(move |bindings| {
             let bindings = bindings.narrow_vars(&query_vars);
             log::trace!("single_query: push result: {}", bindings);
-            result.push(bindings);
+            bindings
+        })
+    }
+
+    /// Writes atoms of the space as S-expressions one atom per line.
+    /// Duplicated atoms are written separately. Returns an error when the
+    /// space contains an atom which cannot be read back by
+    /// [GroundingSpace::from_sexpr]: a grounded atom or a variable made
+    /// unique by [VariableAtom::make_unique] which is written with `#<id>`
+    /// suffix.
+    pub fn to_sexpr(&self) -> Result<String, String> {
+        let mut text = String::new();
+        for atom in self.index.iter() {
+            let unreadable = atom.iter().find(|leaf| match leaf {
+                Atom::Grounded(_) => true,
+                Atom::Variable(var) => var.name().contains('#'),
+                _ => false,
+            });
+            if let Some(leaf) = unreadable {
+                return Err(format!("Atom {} cannot be written as S-expression: {} cannot be parsed back", atom, leaf));
+            }
+            text.push_str(&atom.to_string());
+            text.push('\n');
         }
-        log::debug!("GroundinSpace::single_query: {} result: {}", self, result);
-        result
+        Ok(text)
+    }
+
+    /// Returns statistics of the space index, see [AtomIndex::stats].
+    pub fn index_stats(&self) -> IndexStats {
+        self.index.stats()
+    }
+
+    /// Returns the index which keeps atoms of the space. It allows
+    /// implementing custom query algorithms using lower level primitives of
+    /// the [AtomIndex]. Index is read-only, space should be modified using
+    /// its own methods to keep observers notified.
+    pub fn index(&self) -> &AtomIndex<D> {
+        &self.index
     }
 
     /// Sets the name property for the `GroundingSpace` which can be useful for debugging
@@ -174,9 +880,83 @@ impl<D: DuplicationStrategy> GroundingSpace<D> {
         self.name.as_ref().map(|s| s.as_str())
     }
 
-    #[cfg(test)]
-    fn into_vec(&self) -> Vec<Atom> {
-        self.index.iter().map(|a| a.into_owned()).collect()
+    /// Returns iterator over atoms of the space. Order of the atoms is not
+    /// specified but each atom is returned, duplicates are returned
+    /// separately.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hyperon_atom::{expr, sym, Atom};
+    /// use hyperon::space::grounding::GroundingSpace;
+    ///
+    /// let space = GroundingSpace::from_vec(vec![sym!("A"), expr!("B" "C"), sym!("A")]);
+    ///
+    /// let mut atoms: Vec<Atom> = space.iter().map(|atom| atom.into_owned()).collect();
+    /// atoms.sort_by_key(|atom| atom.to_string());
+    ///
+    /// assert_eq!(atoms, vec![expr!("B" "C"), sym!("A"), sym!("A")]);
+    /// ```
+    pub fn iter(&self) -> impl Iterator<Item=Cow<'_, Atom>> {
+        self.index.iter()
+    }
+
+    /// Returns copies of all atoms of the space. Order of the atoms is not
+    /// specified.
+    pub fn atoms(&self) -> Vec<Atom> {
+        self.iter().map(|a| a.into_owned()).collect()
+    }
+}
+
+fn rewrite_rule(rule: &Atom) -> Option<(&Atom, &Atom)> {
+    match rule {
+        Atom::Expression(expr) => match expr.children() {
+            [op, lhs, rhs] if *op == EQUAL_SYMBOL => Some((lhs, rhs)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn rewrite_atom(rules: &[Atom], atom: &Atom) -> Option<Atom> {
+    rules.iter().find_map(|rule| {
+        let rule = make_variables_unique(rule.clone());
+        let (lhs, rhs) = rewrite_rule(&rule)?;
+        let bindings = matcher::match_atoms(lhs, atom).next()?;
+        let to = matcher::apply_bindings_to_atom_move(rhs.clone(), &bindings);
+        if to == *atom { None } else { Some(to) }
+    })
+}
+
+fn atom_shape(atom: &Atom) -> Atom {
+    match atom {
+        Atom::Expression(expr) => {
+            let children = expr.children().iter().enumerate()
+                .map(|(i, child)| match child {
+                    Atom::Symbol(_) if i == 0 => child.clone(),
+                    _ => atom_shape(child),
+                });
+            Atom::expr(children.collect::<Vec<Atom>>())
+        },
+        _ => Atom::sym("_"),
+    }
+}
+
+// Hashable copy of the atom shape which is used to deduplicate shapes,
+// shape consists of symbols and expressions only.
+#[derive(PartialEq, Eq, Hash)]
+enum ShapeKey {
+    Symbol(SymbolAtom),
+    Expression(Vec<ShapeKey>),
+}
+
+impl ShapeKey {
+    fn new(shape: &Atom) -> Self {
+        match shape {
+            Atom::Symbol(sym) => ShapeKey::Symbol(sym.clone()),
+            Atom::Expression(expr) => ShapeKey::Expression(expr.children().iter().map(ShapeKey::new).collect()),
+            _ => unreachable!("Shape contains only symbols and expressions"),
+        }
     }
 }
 
@@ -188,7 +968,10 @@ impl Space for GroundingSpace {
         GroundingSpace::query(self, query)
     }
     fn atom_count(&self) -> Option<usize> {
-        Some(self.index.iter().count())
+        Some(self.index.len())
+    }
+    fn is_empty(&self) -> Option<bool> {
+        Some(GroundingSpace::is_empty(self))
     }
     fn visit(&self, v: &mut dyn SpaceVisitor) -> Result<(), ()> {
        Ok(self.index.iter().for_each(|atom| v.accept(atom)))
@@ -249,13 +1032,10 @@ impl Grounded for GroundingSpace {
 
 impl CustomMatch for GroundingSpace {
     fn match_(&self, other: &Atom) -> matcher::MatchResultIter {
-        Box::new(self.query(other).into_iter())
+        Box::new(nested_query(self, || self.query(other)).into_iter())
     }
 }
 
-#[cfg(test)]
-use crate::metta::text::*;
-
 #[cfg(test)]
 pub(crate) fn metta_space(text: &str) -> DynSpace {
     let mut space = GroundingSpace::new();
@@ -272,34 +1052,59 @@ mod test {
     use hyperon_atom::matcher::*;
     use hyperon_common::assert_eq_no_order;
 
-    struct SpaceEventCollector {
-        events: Vec<SpaceEvent>,
-    }
+    #[test]
+    fn add_atom() {
+        let mut space = GroundingSpace::new();
+        let observer = space.common.register_observer(SpaceEventCollector::new());
+
+        space.add(expr!("a"));
+        space.add(expr!("b"));
+        space.add(expr!("c"));
+
+        assert_eq_no_order!(space.atoms(), vec![expr!("a"), expr!("b"), expr!("c")]);
+        assert_eq!(observer.borrow().events, vec![SpaceEvent::Add(sym!("a")),
+            SpaceEvent::Add(sym!("b")), SpaceEvent::Add(sym!("c"))]);
+    }
+
+    #[test]
+    fn add_all_atoms() {
+        let mut space = GroundingSpace::new();
+        let observer = space.common.register_observer(SpaceEventCollector::new());
+
+        space.add_all(vec![expr!("a"), expr!("b" "c"), expr!("a")]);
+        space.add_all(vec![]);
 
-    impl SpaceEventCollector {
-        fn new() -> Self {
-            Self{ events: Vec::new() }
-        }
+        assert_eq_no_order!(space.atoms(), vec![expr!("a"), expr!("b" "c"), expr!("a")]);
+        assert_eq!(space.query(&expr!("b" x)), bind_set![{x: sym!("c")}]);
+        assert_eq!(observer.borrow().events, vec![SpaceEvent::BatchAdd(
+            vec![expr!("a"), expr!("b" "c"), expr!("a")])]);
     }
 
-    impl SpaceObserver for SpaceEventCollector {
-        fn notify(&mut self, event: &SpaceEvent) {
-            self.events.push(event.clone());
-        }
+    #[test]
+    fn merge_from_space() {
+        let mut space = GroundingSpace::from_vec(vec![expr!("A" "B"), expr!("B" "C")]);
+        let observer = space.common.register_observer(SpaceEventCollector::new());
+        let other = GroundingSpace::from_vec(vec![expr!("B" "C"), expr!("C" "D")]);
+
+        space.merge_from(&other);
+
+        assert_eq_no_order!(space.atoms(), vec![expr!("A" "B"), expr!("B" "C"),
+            expr!("B" "C"), expr!("C" "D")]);
+        assert_eq!(space.query(&expr!("A" x)), bind_set![{x: sym!("B")}]);
+        assert_eq!(space.query(&expr!("C" x)), bind_set![{x: sym!("D")}]);
+        assert_eq!(observer.borrow().events.len(), 1);
+        assert_eq_no_order!(other.atoms(), vec![expr!("B" "C"), expr!("C" "D")]);
     }
 
     #[test]
-    fn add_atom() {
-        let mut space = GroundingSpace::new();
-        let observer = space.common.register_observer(SpaceEventCollector::new());
+    fn merge_from_space_no_duplication() {
+        let mut space = GroundingSpace::with_strategy(NO_DUPLICATION);
+        space.add(expr!("A" "B"));
+        let other = GroundingSpace::from_vec(vec![expr!("A" "B"), expr!("A" "C")]);
 
-        space.add(expr!("a"));
-        space.add(expr!("b"));
-        space.add(expr!("c"));
+        space.merge_from(&other);
 
-        assert_eq_no_order!(space.into_vec(), vec![expr!("a"), expr!("b"), expr!("c")]);
-        assert_eq!(observer.borrow().events, vec![SpaceEvent::Add(sym!("a")),
-            SpaceEvent::Add(sym!("b")), SpaceEvent::Add(sym!("c"))]);
+        assert_eq_no_order!(space.atoms(), vec![expr!("A" "B"), expr!("A" "C")]);
     }
 
     #[test]
@@ -312,7 +1117,7 @@ mod test {
         space.add(expr!("c"));
         assert_eq!(space.remove(&expr!("b")), true);
 
-        assert_eq_no_order!(space.into_vec(), vec![expr!("a"), expr!("c")]);
+        assert_eq_no_order!(space.atoms(), vec![expr!("a"), expr!("c")]);
         assert_eq!(observer.borrow().events, vec![SpaceEvent::Add(sym!("a")),
             SpaceEvent::Add(sym!("b")), SpaceEvent::Add(sym!("c")),
             SpaceEvent::Remove(sym!("b"))]);
@@ -328,7 +1133,7 @@ mod test {
         space.add(expr!("a"));
         assert_eq!(space.remove(&expr!("a")), true);
 
-        assert_eq_no_order!(space.into_vec(), vec![expr!("a"), expr!("a")]);
+        assert_eq_no_order!(space.atoms(), vec![expr!("a"), expr!("a")]);
         assert_eq!(observer.borrow().events, vec![SpaceEvent::Add(sym!("a")),
             SpaceEvent::Add(sym!("a")), SpaceEvent::Add(sym!("a")),
             SpaceEvent::Remove(sym!("a"))]);
@@ -342,10 +1147,23 @@ mod test {
         space.add(expr!("a"));
         assert_eq!(space.remove(&expr!("b")), false);
 
-        assert_eq_no_order!(space.into_vec(), vec![expr!("a")]);
+        assert_eq_no_order!(space.atoms(), vec![expr!("a")]);
         assert_eq!(observer.borrow().events, vec![SpaceEvent::Add(sym!("a"))]);
     }
 
+    #[test]
+    fn remove_all_atoms() {
+        let mut space = GroundingSpace::from_vec(vec![expr!("a"), expr!("b" "c"), expr!("d"), expr!("a")]);
+        let observer = space.common.register_observer(SpaceEventCollector::new());
+
+        assert_eq!(space.remove_all(&[expr!("a"), expr!("e"), expr!("b" "c")]), 2);
+        assert_eq!(space.remove_all(&[expr!("e")]), 0);
+
+        assert_eq_no_order!(space.atoms(), vec![expr!("d"), expr!("a")]);
+        assert_eq!(observer.borrow().events, vec![SpaceEvent::BatchRemove(
+            vec![expr!("a"), expr!("b" "c")])]);
+    }
+
     #[test]
     fn replace_atom() {
         let mut space = GroundingSpace::new();
@@ -356,12 +1174,25 @@ mod test {
         space.add(expr!("c"));
         assert_eq!(space.replace(&expr!("b"), expr!("d")), true);
 
-        assert_eq_no_order!(space.into_vec(), vec![expr!("a"), expr!("d"), expr!("c")]);
+        assert_eq_no_order!(space.atoms(), vec![expr!("a"), expr!("d"), expr!("c")]);
         assert_eq!(observer.borrow().events, vec![SpaceEvent::Add(sym!("a")),
             SpaceEvent::Add(sym!("b")), SpaceEvent::Add(sym!("c")),
             SpaceEvent::Replace(sym!("b"), sym!("d"))]);
     }
 
+    #[test]
+    fn replace_atom_by_itself() {
+        let mut space = GroundingSpace::new();
+        space.add(expr!("a" "b"));
+        let observer = space.common.register_observer(SpaceEventCollector::new());
+
+        assert_eq!(space.replace(&expr!("a" "b"), expr!("a" "b")), true);
+        assert_eq!(space.replace(&expr!("c"), expr!("c")), false);
+
+        assert_eq_no_order!(space.atoms(), vec![expr!("a" "b")]);
+        assert_eq!(observer.borrow().events, vec![]);
+    }
+
     #[test]
     fn replace_atom_not_found() {
         let mut space = GroundingSpace::new();
@@ -370,7 +1201,7 @@ mod test {
         space.add(expr!("a"));
         assert_eq!(space.replace(&expr!("b"), expr!("d")), false);
 
-        assert_eq_no_order!(space.into_vec(), vec![expr!("a")]);
+        assert_eq_no_order!(space.atoms(), vec![expr!("a")]);
         assert_eq!(observer.borrow().events, vec![SpaceEvent::Add(sym!("a"))]);
     }
 
@@ -383,12 +1214,169 @@ mod test {
         space.replace(&expr!("a"), expr!("b"));
         assert_eq!(space.remove(&expr!("b")), true);
 
-        assert_eq_no_order!(space.into_vec(), Vec::<Atom>::new());
+        assert_eq_no_order!(space.atoms(), Vec::<Atom>::new());
         assert_eq!(observer.borrow().events, vec![SpaceEvent::Add(sym!("a")),
             SpaceEvent::Replace(expr!("a"), expr!("b")),
             SpaceEvent::Remove(expr!("b"))]);
     }
 
+    #[test]
+    fn clear_space() {
+        let mut space = GroundingSpace::new();
+        let observer = space.common.register_observer(SpaceEventCollector::new());
+
+        space.add(expr!("a"));
+        space.add(expr!("b"));
+        space.add(expr!("b"));
+        space.clear();
+
+        assert_eq!(space.atom_count(), Some(0));
+        assert_eq!(space.query(&expr!(x)), BindingsSet::empty());
+        assert_eq_no_order!(observer.borrow().events[3..].to_vec(), vec![SpaceEvent::Remove(sym!("a")),
+            SpaceEvent::Remove(sym!("b")), SpaceEvent::Remove(sym!("b"))]);
+
+        space.add(expr!("c"));
+        assert_eq!(observer.borrow().events.last(), Some(&SpaceEvent::Add(sym!("c"))));
+    }
+
+    #[test]
+    fn retain_atoms() {
+        let mut space = GroundingSpace::new();
+        let observer = space.common.register_observer(SpaceEventCollector::new());
+
+        space.add(expr!("likes" "Sam" "tea"));
+        space.add(expr!("hates" "Sam" "coffee"));
+        space.add(expr!("likes" "Ann" "tea"));
+        space.add(expr!("hates" "Sam" "coffee"));
+        space.add(sym!("likes"));
+        space.retain(|atom| match atom {
+            Atom::Expression(expr) => expr.children().first() == Some(&sym!("likes")),
+            _ => false,
+        });
+
+        assert_eq_no_order!(space.atoms(), vec![expr!("likes" "Sam" "tea"), expr!("likes" "Ann" "tea")]);
+        assert_eq_no_order!(observer.borrow().events[5..].to_vec(), vec![
+            SpaceEvent::Remove(expr!("hates" "Sam" "coffee")),
+            SpaceEvent::Remove(expr!("hates" "Sam" "coffee")),
+            SpaceEvent::Remove(sym!("likes"))]);
+    }
+
+    #[test]
+    fn atom_count_is_updated() {
+        let mut space = GroundingSpace::new();
+        let assert_count = |space: &GroundingSpace, expected: usize| {
+            assert_eq!(space.atom_count(), Some(expected));
+            assert_eq!(space.iter().count(), expected);
+        };
+
+        assert_count(&space, 0);
+        space.add(expr!("a"));
+        space.add(expr!("b" "c"));
+        assert_count(&space, 2);
+        space.add(expr!("a"));
+        space.add(expr!("b" "c"));
+        assert_count(&space, 4);
+        assert!(space.remove(&expr!("a")));
+        assert_count(&space, 3);
+        assert!(!space.remove(&expr!("d")));
+        assert_count(&space, 3);
+        assert!(space.replace(&expr!("b" "c"), expr!("d")));
+        assert_count(&space, 3);
+        space.clear();
+        assert_count(&space, 0);
+    }
+
+    #[test]
+    fn from_vec_with_strategy_no_duplication() {
+        let space = GroundingSpace::from_vec_with_strategy(vec![
+            expr!("a"), expr!("b" "c"), expr!("a"), expr!("b" "c"), expr!("d"),
+        ], NO_DUPLICATION);
+
+        assert_eq!(space.index().len(), 3);
+        assert_eq_no_order!(space.atoms(), vec![expr!("a"), expr!("b" "c"), expr!("d")]);
+
+        let space = GroundingSpace::from_vec_with_strategy(vec![expr!("a"), expr!("a")], ALLOW_DUPLICATION);
+        assert_eq!(space.atom_count(), Some(2));
+    }
+
+    #[test]
+    fn atom_count_no_duplication() {
+        let mut space = GroundingSpace::with_strategy(NO_DUPLICATION);
+
+        space.add(expr!("a"));
+        space.add(expr!("a"));
+        assert_eq!(space.index.len(), 1);
+        assert!(space.remove(&expr!("a")));
+        assert_eq!(space.index.len(), 0);
+        assert_eq!(space.iter().count(), 0);
+    }
+
+    #[test]
+    fn try_add_accepted_atoms() {
+        let mut space = GroundingSpace::new();
+        space.set_constraints(AtomConstraints{ max_depth: Some(2), allow_empty_expr: false });
+
+        assert_eq!(space.try_add(sym!("a")), Ok(()));
+        assert_eq!(space.try_add(expr!("a" ("b" x))), Ok(()));
+
+        assert_eq_no_order!(space.atoms(), vec![sym!("a"), expr!("a" ("b" x))]);
+    }
+
+    #[test]
+    fn try_add_rejected_atoms() {
+        let mut space = GroundingSpace::new();
+        let observer = space.common.register_observer(SpaceEventCollector::new());
+        space.set_constraints(AtomConstraints{ max_depth: Some(2), allow_empty_expr: false });
+
+        assert_eq!(space.try_add(expr!()), Err(AtomError::EmptyExpression));
+        assert_eq!(space.try_add(expr!("a" ())), Err(AtomError::EmptyExpression));
+        assert_eq!(space.try_add(expr!("a" ("b" ("c")))), Err(AtomError::TooDeep{ max_depth: 2 }));
+
+        assert_eq_no_order!(space.atoms(), Vec::<Atom>::new());
+        assert_eq!(observer.borrow().events, vec![]);
+    }
+
+    #[test]
+    fn try_add_empty_expr_allowed() {
+        let mut space = GroundingSpace::new();
+        space.set_constraints(AtomConstraints{ max_depth: None, allow_empty_expr: true });
+
+        assert_eq!(space.try_add(expr!("a" ())), Ok(()));
+        assert_eq_no_order!(space.atoms(), vec![expr!("a" ())]);
+    }
+
+    #[test]
+    fn contains_symbol() {
+        let mut space = GroundingSpace::from_vec(vec![sym!("A"), expr!("B" "C")]);
+
+        assert!(space.contains(&sym!("A")));
+        assert!(!space.contains(&sym!("C")));
+        assert!(space.contains(&expr!("B" "C")));
+        assert!(!space.contains(&expr!("B")));
+
+        space.remove(&sym!("A"));
+        assert!(!space.contains(&sym!("A")));
+    }
+
+    #[test]
+    fn contains_expression_with_variables() {
+        let space = GroundingSpace::from_vec(vec![expr!("A" x), expr!("B" "C")]);
+
+        assert!(space.contains(&expr!("A" x)));
+        assert!(!space.contains(&expr!("A" y)));
+        assert!(!space.contains(&expr!("A" "C")));
+        assert!(!space.contains(&expr!("B" x)));
+        assert!(!space.contains(&expr!(x)));
+    }
+
+    #[test]
+    fn contains_nonhashable_grounded() {
+        let space = GroundingSpace::from_vec(vec![expr!("A" {1})]);
+
+        assert!(space.contains(&expr!("A" {1})));
+        assert!(!space.contains(&expr!("A" {2})));
+    }
+
     #[test]
     fn get_atom_after_removed() {
         let mut space = GroundingSpace::new();
@@ -407,6 +1395,21 @@ mod test {
         assert_eq!(space.atom_count(), Some(0));
     }
 
+    #[test]
+    fn is_empty_space() {
+        let mut space = GroundingSpace::new();
+        assert!(space.is_empty());
+        assert_eq!(Space::is_empty(&space), Some(true));
+
+        space.add(expr!("a" "b"));
+        assert!(!space.is_empty());
+        assert_eq!(Space::is_empty(&space), Some(false));
+
+        space.remove(&expr!("a" "b"));
+        assert!(space.is_empty());
+        assert!(GroundingSpace::from_vec(vec![]).is_empty());
+    }
+
     #[test]
     fn iter_after_remove() {
         let mut space = GroundingSpace::from_vec(vec![expr!("a"), expr!("b"), expr!("c")]);
@@ -417,6 +1420,161 @@ mod test {
         assert_eq_no_order!(atoms, vec![expr!("a"), expr!("c")]);
     }
 
+    #[test]
+    fn shapes_of_mixed_space() {
+        let space = GroundingSpace::from_vec(vec![
+            expr!("edge" "A" "B"),
+            expr!("color" "A" "red"),
+            expr!("edge" "B" x),
+            expr!("color" "B" {1}),
+            expr!("edge" "A" ("pair" "B" "C")),
+            sym!("A"),
+            expr!(("f" "a") "b"),
+        ]);
+
+        assert_eq_no_order!(space.shapes(), vec![
+            expr!("edge" "_" "_"),
+            expr!("color" "_" "_"),
+            expr!("edge" "_" ("pair" "_" "_")),
+            sym!("_"),
+            expr!(("f" "_") "_"),
+        ]);
+    }
+
+    #[test]
+    fn shapes_with_same_text_representation() {
+        let space = GroundingSpace::from_vec(vec![
+            expr!("a" "b"),
+            Atom::expr([Atom::sym("a _")]),
+        ]);
+
+        assert_eq_no_order!(space.shapes(), vec![
+            expr!("a" "_"),
+            Atom::expr([Atom::sym("a _")]),
+        ]);
+    }
+
+    #[test]
+    fn apply_rules_rewrites_atoms() {
+        let mut space = GroundingSpace::from_vec(vec![
+            expr!("parent" "Tom" "Bob"),
+            expr!("parent" "Bob" "Ann"),
+            expr!("age" "Tom" "old"),
+            expr!("likes" "Ann" "tea"),
+        ]);
+        let observer = space.common.register_observer(SpaceEventCollector::new());
+        let rules = GroundingSpace::from_vec(vec![
+            expr!("=" ("parent" x y) ("child" y x)),
+            expr!("=" ("child" x "Tom") ("grandchild-candidate" x)),
+            expr!("=" ("age" x "old") ("age" x "old")),
+            expr!("not-a-rule" x),
+        ]);
+
+        assert_eq!(space.apply_rules(&rules), 3);
+
+        assert_eq_no_order!(space.atoms(), vec![
+            expr!("grandchild-candidate" "Bob"),
+            expr!("child" "Ann" "Bob"),
+            expr!("age" "Tom" "old"),
+            expr!("likes" "Ann" "tea"),
+        ]);
+        assert_eq!(observer.borrow().events.len(), 3);
+    }
+
+    #[test]
+    fn apply_rules_is_bounded() {
+        let mut space = GroundingSpace::from_vec(vec![sym!("A")]);
+        let rules = GroundingSpace::from_vec(vec![
+            expr!("=" "A" "B"),
+            expr!("=" "B" "A"),
+        ]);
+
+        assert_eq!(space.apply_rules(&rules), MAX_REWRITE_ITERATIONS);
+        assert_eq!(space.atoms(), vec![sym!("A")]);
+    }
+
+    #[test]
+    fn from_adjacency() {
+        let mut adj = ListMap::new();
+        adj.insert(sym!("A"), vec![sym!("B"), sym!("C")]);
+        adj.insert(sym!("B"), vec![sym!("C")]);
+        adj.insert(sym!("C"), vec![]);
+
+        let space = GroundingSpace::from_adjacency("edge", &adj);
+
+        assert_eq_no_order!(space.atoms(), vec![expr!("edge" "A" "B"),
+            expr!("edge" "A" "C"), expr!("edge" "B" "C")]);
+        assert_eq_no_order!(space.subst(&expr!("edge" x "C"), &expr!(x)), vec![sym!("A"), sym!("B")]);
+    }
+
+    #[test]
+    fn index_custom_scan() {
+        let space = GroundingSpace::from_vec(vec![
+            expr!("A" "B"), expr!("A" "C"), expr!("D" "E"), sym!("A"),
+        ]);
+        let query = expr!("A" x);
+
+        let scanned: BindingsSet = space.index().iter()
+            .flat_map(|atom| match_atoms(&query, &atom).collect::<Vec<Bindings>>())
+            .collect();
+
+        assert_eq!(scanned, space.query(&query));
+        assert_eq!(space.index().query(&query).count(), 2);
+        assert!(space.index().contains(&expr!("D" "E")));
+    }
+
+    #[test]
+    fn sexpr_round_trip() {
+        let space = GroundingSpace::from_vec(vec![
+            sym!("A"),
+            expr!("B" ("C" ("D" x)) ()),
+            expr!("E" "F"),
+            expr!("E" "F"),
+            sym!("A"),
+        ]);
+
+        let text = space.to_sexpr().unwrap();
+        assert_eq!(text.lines().count(), 5);
+        let restored = GroundingSpace::from_sexpr(&text, &Tokenizer::new()).unwrap();
+
+        assert_eq_no_order!(restored.atoms(), space.atoms());
+    }
+
+    #[test]
+    fn to_sexpr_rejects_unreadable_atoms() {
+        let space = GroundingSpace::from_vec(vec![expr!("A" {1})]);
+        assert!(space.to_sexpr().is_err());
+
+        let space = GroundingSpace::from_vec(vec![
+            Atom::expr([sym!("A"), Atom::Variable(VariableAtom::new("x").make_unique())]),
+        ]);
+        assert!(space.to_sexpr().is_err());
+    }
+
+    #[test]
+    fn from_sexpr_parse_error() {
+        assert!(GroundingSpace::from_sexpr("(A (B)", &Tokenizer::new()).is_err());
+        assert!(GroundingSpace::from_sexpr(")", &Tokenizer::new()).is_err());
+        assert_eq!(GroundingSpace::from_sexpr("", &Tokenizer::new()).unwrap().atoms(), vec![]);
+    }
+
+    #[test]
+    fn index_stats_skewed_heads() {
+        let mut space = GroundingSpace::new();
+        for i in 0..10 {
+            space.add(Atom::expr([sym!("edge"), Atom::sym(i.to_string()), sym!("x")]));
+        }
+        space.add(expr!("edge" "0" "x"));
+        space.add(expr!("color" "a" "red"));
+        space.add(expr!("color" "b" "blue"));
+        space.add(sym!("edge"));
+        space.add(expr!(x "y"));
+        space.add(expr!({1} "y"));
+
+        assert_eq!(space.index_stats(), IndexStats{ atoms: 16, distinct_heads: 2, max_bucket: 12 });
+        assert_eq!(GroundingSpace::new().index_stats(), IndexStats::default());
+    }
+
     #[test]
     fn mut_cloned_atomspace() {
         let mut first = GroundingSpace::new();
@@ -425,8 +1583,8 @@ mod test {
         first.add(expr!("b"));
         second.add(expr!("d"));
 
-        assert_eq_no_order!(first.into_vec(), vec![expr!("b")]);
-        assert_eq_no_order!(second.into_vec(), vec![expr!("d")]);
+        assert_eq_no_order!(first.atoms(), vec![expr!("b")]);
+        assert_eq_no_order!(second.atoms(), vec![expr!("d")]);
     }
 
     #[test]
@@ -526,6 +1684,252 @@ mod test {
         assert_eq!(result, bind_set![{h: expr!("Socrates"), t: expr!("Nil")}]);
     }
 
+    #[test]
+    fn query_with_bindings_seeded_variable() {
+        let space = GroundingSpace::from_vec(vec![
+            expr!("parent" "Tom" "Bob"), expr!("parent" "Tom" "Liz"),
+            expr!("parent" "Bob" "Ann"), expr!("parent" "Liz" "Sam"),
+        ]);
+
+        let result = space.query_with_bindings(&expr!("parent" p c), &bind!{p: sym!("Tom"), z: sym!("Z")});
+        assert_eq!(result, bind_set![
+            {p: sym!("Tom"), c: sym!("Bob"), z: sym!("Z")},
+            {p: sym!("Tom"), c: sym!("Liz"), z: sym!("Z")}]);
+
+        let result = space.query_with_bindings(&expr!("," ("parent" p c) ("parent" c g)),
+            &bind!{p: sym!("Tom")});
+        assert_eq!(result, bind_set![
+            {p: sym!("Tom"), c: sym!("Bob"), g: sym!("Ann")},
+            {p: sym!("Tom"), c: sym!("Liz"), g: sym!("Sam")}]);
+
+        assert_eq!(space.query_with_bindings(&expr!("parent" p c), &bind!{p: sym!("Ann")}),
+            BindingsSet::empty());
+    }
+
+    #[test]
+    fn query_limited_single_query() {
+        let space = GroundingSpace::from_vec(vec![
+            expr!("A" "B"), expr!("A" "C"), expr!("A" "D"), expr!("E" "F"),
+        ]);
+        let query = expr!("A" x);
+
+        let all = space.query(&query);
+        assert_eq!(space.query_limited(&query, 1), bind_set![all[0].clone()]);
+        assert_eq!(space.query_limited(&query, 2).len(), 2);
+        assert_eq!(space.query_limited(&query, 10), all);
+        assert_eq!(space.query_limited(&query, 0), BindingsSet::empty());
+        assert_eq!(space.query_limited(&expr!("G" x), 1), BindingsSet::empty());
+    }
+
+    #[test]
+    fn query_limited_complex_query() {
+        let space = GroundingSpace::from_vec(vec![
+            expr!("A" "B"), expr!("A" "C"), expr!("A" "D"),
+            expr!("B" "1"), expr!("D" "2"), expr!("D" "3"),
+        ]);
+        let query = expr!("," ("A" x) (x y));
+
+        let all = space.query(&query);
+        assert_eq!(all.len(), 3);
+        assert_eq!(space.query_limited(&query, 1), bind_set![all[0].clone()]);
+        assert_eq!(space.query_limited(&query, 2), bind_set![all[0].clone(), all[1].clone()]);
+        assert_eq!(space.query_limited(&query, 5), all);
+    }
+
+    #[test]
+    fn query_limited_narrows_only_needed_results() {
+        let space = GroundingSpace::from_vec((0..100)
+            .flat_map(|i| [Atom::expr([sym!("A"), Atom::sym(format!("B{}", i))]),
+                Atom::expr([Atom::sym(format!("B{}", i)), sym!("C")])])
+            .collect());
+        let narrowed = Rc::new(Cell::new(0));
+        let limited = |query: &Atom, limit| complex_query_limited(query, limit, |query| {
+            let narrowed = narrowed.clone();
+            Box::new(space.single_query_iter(query)
+                .inspect(move |_| narrowed.set(narrowed.get() + 1)))
+        });
+
+        assert_eq!(limited(&expr!("A" x), 10).len(), 10);
+        assert_eq!(narrowed.replace(0), 10);
+        // each of 10 results of ("A" x) is merged with a single result of (x y)
+        assert_eq!(limited(&expr!("," ("A" x) (x y)), 10).len(), 10);
+        assert_eq!(narrowed.replace(0), 20);
+    }
+
+    #[test]
+    fn query_budgeted_single_query() {
+        let space = GroundingSpace::from_vec(vec![
+            expr!("A" "B"), expr!("A" "C"), expr!("A" "D"), expr!("E" "F"),
+        ]);
+
+        assert_eq!(space.query_budgeted(&expr!("A" x), 1000), (space.query(&expr!("A" x)), false));
+        let (result, exhausted) = space.query_budgeted(&expr!("A" x), 5);
+        assert!(exhausted);
+        assert!(result.len() < 3);
+        assert_eq!(space.query_budgeted(&expr!("A" x), 0), (BindingsSet::empty(), true));
+        assert_eq!(space.query_budgeted(&expr!("G" x), 1000), (BindingsSet::empty(), false));
+    }
+
+    #[test]
+    fn query_budgeted_complex_query() {
+        let space = GroundingSpace::from_vec(vec![
+            expr!("A" "B"), expr!("A" "C"), expr!("A" "D"),
+            expr!("B" "1"), expr!("D" "2"), expr!("D" "3"),
+        ]);
+        let query = expr!("," ("A" x) (x y));
+
+        assert_eq!(space.query_budgeted(&query, 1000), (space.query(&query), false));
+        let (result, exhausted) = space.query_budgeted(&query, 10);
+        assert!(exhausted);
+        assert!(result.len() < 3);
+    }
+
+    #[test]
+    fn query_atoms_variable_pattern() {
+        let space = GroundingSpace::from_vec(vec![
+            expr!("likes" "Sam" "tea"),
+            expr!("likes" "Ann" ("green" "tea")),
+            expr!("likes" "Bob" "tea"),
+            expr!("hates" "Sam" "tea"),
+        ]);
+
+        assert_eq_no_order!(space.query_atoms(&expr!("likes" x y)), vec![
+            expr!("likes" "Sam" "tea"),
+            expr!("likes" "Ann" ("green" "tea")),
+            expr!("likes" "Bob" "tea"),
+        ]);
+        assert_eq_no_order!(space.query_atoms(&expr!(x "Sam" "tea")), vec![
+            expr!("likes" "Sam" "tea"),
+            expr!("hates" "Sam" "tea"),
+        ]);
+        assert_eq!(space.query_atoms(&expr!("likes" "Sam" "tea")), vec![expr!("likes" "Sam" "tea")]);
+        assert_eq!(space.query_atoms(&expr!("likes" "Sam" "coffee")), Vec::<Atom>::new());
+    }
+
+    #[test]
+    fn query_first_multiple_matches() {
+        let space = GroundingSpace::from_vec(vec![
+            expr!("A" "B"), expr!("A" "C"), expr!("B" "D"), expr!("C" "E"),
+        ]);
+
+        let all = space.query(&expr!("A" x));
+        let first = space.query_first(&expr!("A" x)).unwrap();
+        assert!(all.iter().any(|b| *b == first));
+
+        let query = expr!("," ("A" x) (x y));
+        let all = space.query(&query);
+        let first = space.query_first(&query).unwrap();
+        assert!(all.iter().any(|b| *b == first));
+    }
+
+    #[test]
+    fn query_first_no_matches() {
+        let space = GroundingSpace::from_vec(vec![expr!("A" "B")]);
+
+        assert_eq!(space.query_first(&expr!("C" x)), None);
+        assert_eq!(space.query_first(&expr!("," ("A" x) (x y))), None);
+    }
+
+    #[test]
+    fn query_sorted_by_variable() {
+        let space = GroundingSpace::from_vec(vec![
+            expr!("age" "Sam" "30"), expr!("age" "Ann" "25"), expr!("age" "Bob" "41"),
+        ]);
+        let x = VariableAtom::new("x");
+        let y = VariableAtom::new("y");
+
+        let result = space.query_sorted_by(&expr!("age" x y), &x);
+        let names: Vec<Atom> = result.iter().map(|b| b.resolve(&x).unwrap()).collect();
+        assert_eq!(names, vec![sym!("Ann"), sym!("Bob"), sym!("Sam")]);
+
+        let result = space.query_sorted_by(&expr!("age" x y), &y);
+        let ages: Vec<Atom> = result.iter().map(|b| b.resolve(&y).unwrap()).collect();
+        assert_eq!(ages, vec![sym!("25"), sym!("30"), sym!("41")]);
+    }
+
+    #[test]
+    fn query_sorted_by_numeric_comparator() {
+        use crate::metta::runner::number::Number;
+
+        let space = GroundingSpace::from_vec(vec![
+            expr!("age" "Sam" {Number::Integer(30)}),
+            expr!("age" "Ann" {Number::Integer(9)}),
+            expr!("age" "Bob" {Number::Integer(100)}),
+        ]);
+        let x = VariableAtom::new("x");
+        let y = VariableAtom::new("y");
+        let as_f64 = |atom: &Atom| -> f64 { Number::from_atom(atom).unwrap().into() };
+
+        let result = space.query_sorted_by_cmp(&expr!("age" x y), &y,
+            |a, b| as_f64(a).total_cmp(&as_f64(b)));
+        let names: Vec<Atom> = result.iter().map(|b| b.resolve(&x).unwrap()).collect();
+        assert_eq!(names, vec![sym!("Ann"), sym!("Sam"), sym!("Bob")]);
+    }
+
+    #[test]
+    fn count_matches_symbol() {
+        let space = GroundingSpace::from_vec(vec![sym!("A"), sym!("A"), sym!("B")]);
+
+        assert_eq!(space.count_matches(&sym!("A")), 2);
+        assert_eq!(space.count_matches(&sym!("B")), 1);
+    }
+
+    #[test]
+    fn count_matches_variable() {
+        let space = GroundingSpace::from_vec(vec![
+            expr!("A" "B"), expr!("A" "C"), expr!("A" "D"),
+            expr!("B" "1"), expr!("D" "2"), expr!("D" "3"),
+        ]);
+
+        assert_eq!(space.count_matches(&expr!("A" x)), 3);
+        assert_eq!(space.count_matches(&expr!(x y)), space.query(&expr!(x y)).len());
+        let query = expr!("," ("A" x) (x y));
+        assert_eq!(space.count_matches(&query), space.query(&query).len());
+        assert_eq!(space.count_matches(&query), 3);
+    }
+
+    #[test]
+    fn count_matches_empty_result() {
+        let space = GroundingSpace::from_vec(vec![expr!("A" "B")]);
+
+        assert_eq!(space.count_matches(&expr!("C" x)), 0);
+        assert_eq!(space.count_matches(&expr!("," ("A" x) (x y))), 0);
+        assert_eq!(GroundingSpace::new().count_matches(&expr!(x)), 0);
+    }
+
+    #[test]
+    fn query_most_specific_overlapping_facts() {
+        let space = GroundingSpace::from_vec(vec![
+            expr!("likes" x y),
+            expr!("likes" "Sam" y),
+            expr!("likes" x "tea"),
+            expr!("likes" "Sam" "tea"),
+            expr!("likes" "Sam" "tea"),
+            expr!("likes" "Ann" "tea"),
+        ]);
+
+        assert_eq!(space.query_most_specific(&expr!("likes" "Sam" z)),
+            vec![expr!("likes" "Sam" "tea"), expr!("likes" "Sam" "tea")]);
+        assert_eq_no_order!(space.query_most_specific(&expr!("likes" "Bob" z)),
+            vec![expr!("likes" "Bob" "tea")]);
+        assert_eq!(space.query_most_specific(&expr!("hates" x y)), Vec::<Atom>::new());
+    }
+
+    #[test]
+    fn query_most_specific_same_variable_names() {
+        let space = GroundingSpace::from_vec(vec![
+            expr!("likes" x y),
+            expr!("likes" "Sam" y),
+            expr!("f" x x),
+            expr!("f" x y),
+        ]);
+
+        assert_eq!(space.query_most_specific(&expr!("likes" y "tea")),
+            vec![expr!("likes" "Sam" "tea")]);
+        assert_eq!(space.query_most_specific(&expr!("f" "a" "a")),
+            vec![expr!("f" "a" "a")]);
+    }
+
     #[test]
     fn cleanup_observer() {
         let mut space = GroundingSpace::new();
@@ -536,7 +1940,7 @@ mod test {
 
         space.add(expr!("a"));
 
-        assert_eq_no_order!(space.into_vec(), vec![expr!("a")]);
+        assert_eq_no_order!(space.atoms(), vec![expr!("a")]);
         assert_eq!(space.common.observers.borrow().len(), 0);
     }
 
@@ -6,12 +6,12 @@ pub mod module;
 
 use std::fmt::Display;
 use std::rc::{Rc, Weak};
-use std::cell::{RefCell, Ref, RefMut};
+use std::cell::{Cell, RefCell, Ref, RefMut};
 use std::borrow::Cow;
 
 use hyperon_common::FlexRef;
 use hyperon_atom::*;
-use hyperon_atom::matcher::{BindingsSet, apply_bindings_to_atom_move};
+use hyperon_atom::matcher::{Bindings, BindingsSet, apply_bindings_to_atom_move};
 use hyperon_atom::subexpr::split_expr;
 
 /// Symbol to concatenate queries to space.
@@ -26,6 +26,10 @@ pub enum SpaceEvent {
     Remove(Atom),
     /// First atom is replaced by the second one.
     Replace(Atom, Atom),
+    /// Atoms are added into a space by a single operation.
+    BatchAdd(Vec<Atom>),
+    /// Atoms are removed from a space by a single operation.
+    BatchRemove(Vec<Atom>),
 }
 
 /// Space modification event observer trait.
@@ -63,6 +67,45 @@ pub enum SpaceEvent {
 pub trait SpaceObserver {
     /// Notifies about space modification.
     fn notify(&mut self, event: &SpaceEvent);
+
+    /// Notifies about space modification and reports whether the event was
+    /// handled successfully. Default implementation calls [SpaceObserver::notify]
+    /// and always succeeds.
+    fn try_notify(&mut self, event: &SpaceEvent) -> Result<(), ObserverError> {
+        self.notify(event);
+        Ok(())
+    }
+
+    /// Notifies about a batch of space modifications when events batching is
+    /// enabled by [SpaceCommon::set_batch_threshold]. Events are passed in
+    /// order they happened. Default implementation calls
+    /// [SpaceObserver::try_notify] for each event and stops on the first
+    /// error.
+    fn notify_batch(&mut self, events: &[SpaceEvent]) -> Result<(), ObserverError> {
+        events.iter().try_for_each(|event| self.try_notify(event))
+    }
+}
+
+/// Error reported by an observer which failed to handle a [SpaceEvent].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObserverError(pub String);
+
+impl Display for ObserverError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<&str> for ObserverError {
+    fn from(msg: &str) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+impl From<String> for ObserverError {
+    fn from(msg: String) -> Self {
+        Self(msg)
+    }
 }
 
 /// A reference to a SpaceObserver that has been registered with a Space
@@ -97,6 +140,8 @@ impl<T: SpaceObserver> From<Rc<RefCell<T>>> for SpaceObserverRef<T> {
 #[derive(Default)]
 pub struct SpaceCommon {
     observers: RefCell<Vec<Weak<RefCell<dyn SpaceObserver>>>>,
+    batch_threshold: Cell<Option<usize>>,
+    batch: RefCell<Vec<SpaceEvent>>,
 }
 impl SpaceCommon {
     /// Registers space modifications `observer`. Observer is automatically deregistered when
@@ -110,11 +155,60 @@ impl SpaceCommon {
     }
 
     /// Notifies all registered observers about space modification `event`.
-    pub fn notify_all_observers(&self, event: &SpaceEvent) {
+    /// An observer which returns an error or panics doesn't prevent other
+    /// observers from being notified. Returns errors of the failed observers.
+    /// When events batching is enabled the event is queued instead and
+    /// errors are returned only when the queue is flushed.
+    pub fn notify_all_observers(&self, event: &SpaceEvent) -> Vec<ObserverError> {
+        match self.batch_threshold.get() {
+            None => self.notify_observers(|observer| observer.try_notify(event)),
+            Some(threshold) => {
+                let len = {
+                    let mut batch = self.batch.borrow_mut();
+                    batch.push(event.clone());
+                    batch.len()
+                };
+                if len >= threshold {
+                    self.flush()
+                } else {
+                    Vec::new()
+                }
+            },
+        }
+    }
+
+    /// Enables events batching when `threshold` is not `None`. Events are
+    /// queued and passed to [SpaceObserver::notify_batch] when `threshold`
+    /// events are collected or [SpaceCommon::flush] is called. Passing
+    /// `None` disables batching and flushes queued events. Events which are
+    /// still queued when [SpaceCommon] is dropped are flushed as well.
+    pub fn set_batch_threshold(&self, threshold: Option<usize>) -> Vec<ObserverError> {
+        self.batch_threshold.set(threshold);
+        self.flush()
+    }
+
+    /// Passes all queued events to the observers. Returns errors of the
+    /// failed observers.
+    pub fn flush(&self) -> Vec<ObserverError> {
+        let events = std::mem::take(&mut *self.batch.borrow_mut());
+        if events.is_empty() {
+            return Vec::new();
+        }
+        self.notify_observers(|observer| observer.notify_batch(&events))
+    }
+
+    fn notify_observers<F>(&self, notify: F) -> Vec<ObserverError>
+    where
+        F: Fn(&mut dyn SpaceObserver) -> Result<(), ObserverError>,
+    {
         let mut cleanup = false;
+        let mut errors = Vec::new();
         for observer in self.observers.borrow_mut().iter() {
             if let Some(observer) = observer.upgrade() {
-                observer.borrow_mut().notify(event);
+                if let Err(err) = Self::notify_observer(&observer, &notify) {
+                    log::error!("SpaceCommon::notify_all_observers: observer failed to handle event: {}", err);
+                    errors.push(err);
+                }
             } else {
                 cleanup = true;
             }
@@ -122,6 +216,22 @@ impl SpaceCommon {
         if cleanup {
             self.observers.borrow_mut().retain(|w| w.strong_count() > 0);
         }
+        errors
+    }
+
+    fn notify_observer<F>(observer: &RefCell<dyn SpaceObserver>, notify: &F) -> Result<(), ObserverError>
+    where
+        F: Fn(&mut dyn SpaceObserver) -> Result<(), ObserverError>,
+    {
+        let mut observer = observer.try_borrow_mut()
+            .map_err(|_| ObserverError::from("Observer is already borrowed"))?;
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| notify(&mut *observer)))
+            .unwrap_or_else(|panic| {
+                let msg = panic.downcast_ref::<&str>().map(|s| s.to_string())
+                    .or_else(|| panic.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "Observer panicked".to_string());
+                Err(ObserverError(msg))
+            })
     }
 }
 
@@ -131,10 +241,20 @@ impl Clone for SpaceCommon {
             //We don't want to clone observers when a space is cloned, as that leads to a situation
             // where an observer can't know which space an event pertains to
             observers: RefCell::new(vec![]),
+            // Batching is a setting of the space thus it is kept, but queued
+            // events pertain to the original space and are not copied
+            batch_threshold: Cell::new(self.batch_threshold.get()),
+            batch: RefCell::new(vec![]),
         }
     }
 }
 
+impl Drop for SpaceCommon {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
 /// An interface for visiting space atoms.
 pub trait SpaceVisitor {
     /// Method is called by [Space::visit] implementation for each atom from the atomspace.
@@ -156,7 +276,9 @@ pub trait Space: std::fmt::Debug + std::fmt::Display {
     /// Executes `query` on the space and returns variable bindings found.
     /// Query may include sub-queries glued by [grounding::COMMA_SYMBOL] symbol. 
     /// Each [Bindings](crate::atom::matcher::Bindings) instance in the returned [BindingsSet]
-    /// represents single result.
+    /// represents single result. When query recurses through nested spaces
+    /// deeper than [max_query_depth] the results of the too deep nested query
+    /// are silently dropped, use [checked_query] to get an error instead.
     ///
     /// # Examples
     ///
@@ -203,6 +325,12 @@ pub trait Space: std::fmt::Debug + std::fmt::Display {
         None
     }
 
+    /// Returns true if the space has no atoms, or None if this can't be
+    /// determined. Default implementation is based on [Space::atom_count].
+    fn is_empty(&self) -> Option<bool> {
+        self.atom_count().map(|count| count == 0)
+    }
+
     /// Visit each atom of the space and call [SpaceVisitor::accept] method.
     /// This method is optional. Return `Err(())` if method is not implemented.
     /// `Cow<Atom>` is used to allow passing both references and values. First
@@ -278,6 +406,9 @@ pub trait SpaceMut: Space {
     fn as_any_mut(&mut self) -> &mut dyn std::any::Any;
 }
 
+/// Shared reference to a space which can be embedded into another space as
+/// a grounded atom. Matching such atom executes a nested query on the space,
+/// see [Space::query] about how too deep nested queries are handled.
 #[derive(Clone)]
 pub struct DynSpace(Rc<RefCell<dyn SpaceMut>>);
 
@@ -333,7 +464,180 @@ impl hyperon_atom::Grounded for DynSpace {
 
 impl CustomMatch for DynSpace {
     fn match_(&self, other: &Atom) -> matcher::MatchResultIter {
-        Box::new(self.borrow().query(other).into_iter())
+        Box::new(nested_query(self, || self.borrow().query(other)).into_iter())
+    }
+}
+
+/// Default maximal depth of the queries recursing through spaces which are
+/// embedded into other spaces as grounded atoms.
+pub const DEFAULT_MAX_QUERY_DEPTH: usize = 64;
+
+thread_local! {
+    static QUERY_DEPTH: Cell<usize> = const { Cell::new(0) };
+    static MAX_QUERY_DEPTH: Cell<usize> = const { Cell::new(DEFAULT_MAX_QUERY_DEPTH) };
+    static QUERY_DEPTH_EXCEEDED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Sets maximal depth of the nested space queries for the current thread.
+/// When a query recurses through embedded spaces deeper than `depth` the
+/// nested query returns no results and [QueryDepthExceeded] is reported by
+/// [checked_query]. The limit is kept in a thread local variable, so it
+/// affects only queries executed by the current thread, other threads keep
+/// their own limit which is [DEFAULT_MAX_QUERY_DEPTH] unless it is set there.
+pub fn set_max_query_depth(depth: usize) {
+    MAX_QUERY_DEPTH.with(|max| max.set(depth));
+}
+
+/// Returns maximal depth of the nested space queries for the current thread,
+/// see [set_max_query_depth].
+pub fn max_query_depth() -> usize {
+    MAX_QUERY_DEPTH.with(|max| max.get())
+}
+
+/// Error which is returned when query recursion via nested spaces exceeds
+/// [max_query_depth].
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryDepthExceeded {
+    pub max_depth: usize,
+}
+
+impl Display for QueryDepthExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Maximal depth of nested space queries {} is exceeded", self.max_depth)
+    }
+}
+
+/// Executes `query` and returns an error if it recursed through nested spaces
+/// deeper than [max_query_depth]. Partial results are dropped in this case.
+/// It can be called from inside of another query, then only nested queries
+/// executed by `query` are checked, and the outer [checked_query] reports
+/// an error as well because its results are also truncated. `query` can
+/// return any value built from the query results, for instance
+/// [Space::subst] results.
+pub fn checked_query<R, F>(query: F) -> Result<R, QueryDepthExceeded>
+where
+    F: FnOnce() -> R,
+{
+    let outer_exceeded = QUERY_DEPTH_EXCEEDED.with(|exceeded| exceeded.replace(false));
+    let result = query();
+    let exceeded = QUERY_DEPTH_EXCEEDED.with(|exceeded| exceeded.replace(outer_exceeded || exceeded.get()));
+    if exceeded {
+        Err(QueryDepthExceeded{ max_depth: max_query_depth() })
+    } else {
+        Ok(result)
+    }
+}
+
+/// Executes query of the space which is matched as a grounded atom keeping
+/// track of the nesting depth.
+fn nested_query<S, F>(space: &S, query: F) -> BindingsSet
+where
+    S: Display + ?Sized,
+    F: FnOnce() -> BindingsSet,
+{
+    struct DepthGuard();
+    impl Drop for DepthGuard {
+        fn drop(&mut self) {
+            QUERY_DEPTH.with(|depth| depth.set(depth.get() - 1));
+        }
+    }
+
+    let depth = QUERY_DEPTH.with(|depth| { depth.set(depth.get() + 1); depth.get() });
+    let _guard = DepthGuard();
+    if depth > max_query_depth() {
+        log::error!("nested_query: {} query depth {} exceeds the limit", space, depth);
+        QUERY_DEPTH_EXCEEDED.with(|exceeded| exceeded.set(true));
+        BindingsSet::empty()
+    } else {
+        query()
+    }
+}
+
+/// Iterator over results of a simple query which is used by
+/// [complex_query_limited] and [complex_query_count]. It can borrow the
+/// query, thus variables of the query are not copied to narrow results.
+type QueryIter<'a> = Box<dyn Iterator<Item=Bindings> + 'a>;
+
+/// Same as [complex_query] but stops as soon as `limit` results are found.
+/// Sub-queries glued by [COMMA_SYMBOL] are evaluated depth first and the
+/// limit is applied to the final merged results. Results returned by
+/// `single_query` are pulled only while they are needed, but it is up to
+/// `single_query` to calculate them lazily. For example
+/// [grounding::GroundingSpace] index calculates all matches of the
+/// sub-query before returning the first one, thus the work done by the
+/// index is not bounded by `limit`.
+fn complex_query_limited<F>(query: &Atom, limit: usize, single_query: F) -> BindingsSet
+where
+    F: for<'q> Fn(&'q Atom) -> QueryIter<'q>,
+{
+    log::debug!("complex_query_limited: query: {}, limit: {}", query, limit);
+    match split_expr(query) {
+        Some((sym @ Atom::Symbol(_), args)) if *sym == COMMA_SYMBOL => {
+            let args: Vec<&Atom> = args.collect();
+            let mut result = BindingsSet::empty();
+            conjunction_query_limited(&args, Bindings::new(), limit, &single_query, &mut result);
+            result
+        },
+        _ => single_query(query).take(limit).collect(),
+    }
+}
+
+fn conjunction_query_limited<F>(args: &[&Atom], prev: Bindings, limit: usize,
+    single_query: &F, result: &mut BindingsSet)
+where
+    F: for<'q> Fn(&'q Atom) -> QueryIter<'q>,
+{
+    if result.len() >= limit {
+        return;
+    }
+    match args.split_first() {
+        None => result.push(prev),
+        Some((query, rest)) => {
+            let query = matcher::apply_bindings_to_atom_move((*query).clone(), &prev);
+            for next in single_query(&query) {
+                for bindings in next.merge(&prev) {
+                    conjunction_query_limited(rest, bindings, limit, single_query, result);
+                    if result.len() >= limit {
+                        return;
+                    }
+                }
+            }
+        },
+    }
+}
+
+/// Counts results of the `query` without collecting them into [BindingsSet].
+/// `single_count` counts results of the simple query without sub-queries.
+/// Sub-queries glued by [COMMA_SYMBOL] are evaluated depth first using
+/// `single_query` and the merged results are counted.
+fn complex_query_count<F, C>(query: &Atom, single_query: F, single_count: C) -> usize
+where
+    F: for<'q> Fn(&'q Atom) -> QueryIter<'q>,
+    C: Fn(&Atom) -> usize,
+{
+    log::debug!("complex_query_count: query: {}", query);
+    match split_expr(query) {
+        Some((sym @ Atom::Symbol(_), args)) if *sym == COMMA_SYMBOL => {
+            let args: Vec<&Atom> = args.collect();
+            conjunction_query_count(&args, Bindings::new(), &single_query)
+        },
+        _ => single_count(query),
+    }
+}
+
+fn conjunction_query_count<F>(args: &[&Atom], prev: Bindings, single_query: &F) -> usize
+where
+    F: for<'q> Fn(&'q Atom) -> QueryIter<'q>,
+{
+    match args.split_first() {
+        None => 1,
+        Some((query, rest)) => {
+            let query = matcher::apply_bindings_to_atom_move((*query).clone(), &prev);
+            single_query(&query)
+                .flat_map(|next| next.merge(&prev))
+                .map(|bindings| conjunction_query_count(rest, bindings, single_query))
+                .sum()
+        },
     }
 }
 
@@ -366,3 +670,194 @@ where
         _ => single_query(query),
     }
 }
+
+#[cfg(test)]
+pub(crate) struct SpaceEventCollector {
+    pub events: Vec<SpaceEvent>,
+}
+
+#[cfg(test)]
+impl SpaceEventCollector {
+    pub fn new() -> Self {
+        Self{ events: Vec::new() }
+    }
+}
+
+#[cfg(test)]
+impl SpaceObserver for SpaceEventCollector {
+    fn notify(&mut self, event: &SpaceEvent) {
+        self.events.push(event.clone());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::space::grounding::GroundingSpace;
+
+    struct FailingObserver {}
+
+    impl SpaceObserver for FailingObserver {
+        fn notify(&mut self, _event: &SpaceEvent) {}
+        fn try_notify(&mut self, _event: &SpaceEvent) -> Result<(), ObserverError> {
+            Err("FailingObserver error".into())
+        }
+    }
+
+    struct PanickingObserver {}
+
+    impl SpaceObserver for PanickingObserver {
+        fn notify(&mut self, _event: &SpaceEvent) {
+            panic!("PanickingObserver panic");
+        }
+    }
+
+    #[test]
+    fn notify_all_observers_reports_failed_observers() {
+        let common = SpaceCommon::default();
+        let _failing = common.register_observer(FailingObserver{});
+        let _panicking = common.register_observer(PanickingObserver{});
+        let collecting = common.register_observer(SpaceEventCollector::new());
+
+        let errors = common.notify_all_observers(&SpaceEvent::Add(sym!("A")));
+
+        assert_eq!(errors, vec![ObserverError::from("FailingObserver error"),
+            ObserverError::from("PanickingObserver panic")]);
+        assert_eq!(collecting.borrow().events, vec![SpaceEvent::Add(sym!("A"))]);
+    }
+
+    struct BatchObserver {
+        batches: Vec<Vec<SpaceEvent>>,
+    }
+
+    impl SpaceObserver for BatchObserver {
+        fn notify(&mut self, event: &SpaceEvent) {
+            self.batches.push(vec![event.clone()]);
+        }
+        fn notify_batch(&mut self, events: &[SpaceEvent]) -> Result<(), ObserverError> {
+            self.batches.push(events.to_vec());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn batched_events_are_delivered_on_threshold() {
+        let common = SpaceCommon::default();
+        let batch = common.register_observer(BatchObserver{ batches: Vec::new() });
+        let collecting = common.register_observer(SpaceEventCollector::new());
+        common.set_batch_threshold(Some(2));
+
+        common.notify_all_observers(&SpaceEvent::Add(sym!("A")));
+        assert!(batch.borrow().batches.is_empty());
+        common.notify_all_observers(&SpaceEvent::Remove(sym!("A")));
+        common.notify_all_observers(&SpaceEvent::Add(sym!("B")));
+
+        assert_eq!(batch.borrow().batches, vec![
+            vec![SpaceEvent::Add(sym!("A")), SpaceEvent::Remove(sym!("A"))]]);
+        assert_eq!(collecting.borrow().events, vec![SpaceEvent::Add(sym!("A")),
+            SpaceEvent::Remove(sym!("A"))]);
+
+        common.flush();
+        assert_eq!(batch.borrow().batches[1..], vec![vec![SpaceEvent::Add(sym!("B"))]]);
+
+        common.notify_all_observers(&SpaceEvent::Add(sym!("C")));
+        common.set_batch_threshold(None);
+        common.notify_all_observers(&SpaceEvent::Add(sym!("D")));
+        assert_eq!(batch.borrow().batches[2..], vec![vec![SpaceEvent::Add(sym!("C"))],
+            vec![SpaceEvent::Add(sym!("D"))]]);
+        assert_eq!(collecting.borrow().events.len(), 5);
+    }
+
+    #[test]
+    fn batched_events_report_failed_observers() {
+        let common = SpaceCommon::default();
+        let _failing = common.register_observer(FailingObserver{});
+        common.set_batch_threshold(Some(10));
+
+        assert_eq!(common.notify_all_observers(&SpaceEvent::Add(sym!("A"))), vec![]);
+        assert_eq!(common.flush(), vec![ObserverError::from("FailingObserver error")]);
+        assert_eq!(common.flush(), vec![]);
+    }
+
+    #[test]
+    fn batched_events_are_flushed_on_drop() {
+        let common = SpaceCommon::default();
+        let batch = common.register_observer(BatchObserver{ batches: Vec::new() });
+        common.set_batch_threshold(Some(10));
+
+        common.notify_all_observers(&SpaceEvent::Add(sym!("A")));
+        let clone = common.clone();
+        drop(common);
+
+        assert_eq!(batch.borrow().batches, vec![vec![SpaceEvent::Add(sym!("A"))]]);
+        assert_eq!(clone.batch.borrow().len(), 0);
+    }
+
+    #[test]
+    fn space_mutation_succeeds_when_observer_fails() {
+        let mut space = GroundingSpace::new();
+        let _failing = space.common().register_observer(FailingObserver{});
+        let collecting = space.common().register_observer(SpaceEventCollector::new());
+
+        space.add(sym!("A"));
+
+        assert_eq!(space.query(&sym!("A")), BindingsSet::single());
+        assert_eq!(collecting.borrow().events, vec![SpaceEvent::Add(sym!("A"))]);
+    }
+
+    #[test]
+    fn is_empty_default_implementation() {
+        let space = DynSpace::new(GroundingSpace::new());
+        assert_eq!(space.borrow().is_empty(), Some(true));
+
+        space.borrow_mut().add(sym!("A"));
+        assert_eq!(space.borrow().is_empty(), Some(false));
+    }
+
+    #[test]
+    fn checked_query_cyclic_nested_spaces() {
+        let space = DynSpace::new(GroundingSpace::new());
+        space.borrow_mut().add(expr!("A" "B"));
+        space.borrow_mut().add(Atom::gnd(space.clone()));
+
+        let result = checked_query(|| space.borrow().query(&expr!("C" x)));
+
+        assert_eq!(result, Err(QueryDepthExceeded{ max_depth: DEFAULT_MAX_QUERY_DEPTH }));
+    }
+
+    #[test]
+    fn checked_query_configurable_depth() {
+        let inner = DynSpace::new(GroundingSpace::new());
+        inner.borrow_mut().add(expr!("A" "B"));
+        let middle = DynSpace::new(GroundingSpace::new());
+        middle.borrow_mut().add(Atom::gnd(inner));
+        let outer = DynSpace::new(GroundingSpace::new());
+        outer.borrow_mut().add(Atom::gnd(middle));
+
+        set_max_query_depth(1);
+        let result = checked_query(|| outer.borrow().query(&expr!("A" x)));
+        assert_eq!(result, Err(QueryDepthExceeded{ max_depth: 1 }));
+
+        set_max_query_depth(2);
+        let result = checked_query(|| outer.borrow().query(&expr!("A" x)));
+        assert_eq!(result, Ok(bind_set![{x: sym!("B")}]));
+        set_max_query_depth(DEFAULT_MAX_QUERY_DEPTH);
+    }
+
+    #[test]
+    fn checked_query_inside_nested_query_ignores_stale_error() {
+        let cyclic = DynSpace::new(GroundingSpace::new());
+        cyclic.borrow_mut().add(Atom::gnd(cyclic.clone()));
+        let space = DynSpace::new(GroundingSpace::from_vec(vec![expr!("A" "B")]));
+
+        // unchecked query exceeds the depth and drops results silently
+        assert_eq!(cyclic.borrow().query(&expr!("C" x)), BindingsSet::empty());
+        let mut result = None;
+        nested_query(&space, || {
+            result = Some(checked_query(|| space.borrow().query(&expr!("A" x))));
+            BindingsSet::empty()
+        });
+
+        assert_eq!(result, Some(Ok(bind_set![{x: sym!("B")}])));
+    }
+}